@@ -0,0 +1,65 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use dgraph_query_lib::condition::{Condition, ConditionValue};
+use dgraph_query_lib::predicate::{Edge, Field, Predicate};
+use dgraph_query_lib::query::Query;
+use dgraph_query_lib::queryblock::QueryBlockType;
+use dgraph_query_lib::{EdgeBuilder, PrettyConfig, QueryBlockBuilder, QueryBuilder, ToQueryString};
+
+fn nested_edge(depth: usize) -> Edge {
+    let mut predicates = (0..10)
+        .map(|i| Predicate::Field(Field::new(&format!("field_{}", i))))
+        .collect::<Vec<_>>();
+
+    if depth > 0 {
+        predicates.push(Predicate::Edge(nested_edge(depth - 1)));
+    }
+
+    EdgeBuilder::default()
+        .name(format!("edge_{}", depth))
+        .filter(Condition::EQ("name".into(), ConditionValue::string("value")))
+        .predicates(predicates)
+        .build().unwrap()
+}
+
+fn deep_query(depth: usize) -> Query {
+    QueryBuilder::default()
+        .query_blocks(vec![
+            QueryBlockBuilder::default()
+                .query_type(QueryBlockType::query())
+                .root_filter(Condition::has("name"))
+                .predicates(vec![Predicate::Edge(nested_edge(depth))])
+                .build().unwrap()
+        ])
+        .build().unwrap()
+}
+
+fn wide_query(uids: usize) -> Query {
+    let uids = (0..uids).map(|uid| format!("0x{:x}", uid).as_str().into()).collect();
+
+    QueryBuilder::default()
+        .query_blocks(vec![
+            QueryBlockBuilder::default()
+                .query_type(QueryBlockType::query())
+                .root_filter(Condition::UID(uids))
+                .filter(Condition::EQ("tags".into(), ConditionValue::num_arr((0..1000).collect())))
+                .predicates(vec![Predicate::Field(Field::new("uid"))])
+                .build().unwrap()
+        ])
+        .build().unwrap()
+}
+
+fn render(c: &mut Criterion) {
+    let deep = deep_query(200);
+    let wide = wide_query(10_000);
+
+    c.bench_function("render deep query", |b| b.iter(|| deep.to_query_string()));
+    c.bench_function("render wide query", |b| b.iter(|| wide.to_query_string()));
+    c.bench_function("write wide query to io", |b| b.iter(|| {
+        let mut bytes = Vec::new();
+        wide.write_query_io(&mut bytes, &PrettyConfig::default()).unwrap();
+        bytes
+    }));
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -0,0 +1,206 @@
+use crate::condition::Condition;
+use crate::names::Alias;
+use crate::predicate::{EdgeBuilder, Predicate};
+use crate::schema::Schema;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::Path;
+use std::{env, fs, io};
+
+// generates a module with one unit struct per schema type. scalar predicates
+// become `Type::name() -> Predicate` and uid predicates take a closure that
+// selects the edge's children, so using an edge as a field (or the other way
+// around) no longer compiles. names that come out the same in rust (`firstName`
+// and `first_name`, or a type declared twice) get a numbered suffix. edge functions
+// return the error `Selection::edge` reports for an invalid alias or filter
+pub fn generate(schema: &Schema) -> String {
+    let mut out = String::from("// generated from a dgraph schema by dgraph_query_lib::codegen, do not edit\n");
+    let mut type_names = HashSet::new();
+
+    for definition in &schema.definitions {
+        let type_name = unique_name(rust_type_name(definition.name()), &mut type_names);
+        let mut fn_names = HashSet::new();
+
+        writeln!(out).unwrap();
+        writeln!(out, "#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]").unwrap();
+        writeln!(out, "#[allow(non_camel_case_types)]").unwrap();
+        writeln!(out, "pub struct {};", type_name).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "#[allow(dead_code)]").unwrap();
+        writeln!(out, "impl {} {{", type_name).unwrap();
+        writeln!(out, "    pub const TYPE: &'static str = {:?};", definition.name()).unwrap();
+
+        for predicate in definition.predicates() {
+            if predicate.name().starts_with("dgraph.") {
+                continue;
+            }
+
+            let function = unique_name(rust_fn_name(predicate.name()), &mut fn_names);
+            writeln!(out).unwrap();
+
+            if predicate.predicate_type().is_uid() {
+                writeln!(
+                    out,
+                    "    pub fn {}(select: impl FnOnce(&mut ::dgraph_query_lib::codegen::Selection)) -> Result<::dgraph_query_lib::predicate::Predicate, String> {{",
+                    function
+                ).unwrap();
+                writeln!(out, "        ::dgraph_query_lib::codegen::Selection::edge({:?}, select)", predicate.name()).unwrap();
+            } else {
+                writeln!(out, "    pub fn {}() -> ::dgraph_query_lib::predicate::Predicate {{", function).unwrap();
+                writeln!(
+                    out,
+                    "        ::dgraph_query_lib::predicate::Predicate::Field(::dgraph_query_lib::predicate::Field::new({:?}))",
+                    predicate.name()
+                ).unwrap();
+            }
+
+            writeln!(out, "    }}").unwrap();
+        }
+
+        writeln!(out, "}}").unwrap();
+    }
+
+    out
+}
+
+// for build scripts: writes the generated module to `$OUT_DIR/<file_name>`, to be
+// pulled in with `include!(concat!(env!("OUT_DIR"), "/<file_name>"))`
+pub fn generate_to_out_dir(schema: &Schema, file_name: &str) -> io::Result<()> {
+    let out_dir = env::var_os("OUT_DIR")
+        .ok_or_else(|| io::Error::other("OUT_DIR is not set, generate_to_out_dir is meant to be called from a build script"))?;
+
+    generate_to_file(schema, Path::new(&out_dir).join(file_name))
+}
+
+pub fn generate_to_file(schema: &Schema, path: impl AsRef<Path>) -> io::Result<()> {
+    fs::write(path, generate(schema))
+}
+
+// runs of characters that can't appear in an identifier become a single `_`
+fn identifier_words(name: &str) -> Vec<&str> {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn rust_type_name(name: &str) -> String {
+    escape_identifier(identifier_words(name).join("_"))
+}
+
+// `firstName` and `http://schema.org/givenName` become `first_name` and `http_schema_org_given_name`
+fn rust_fn_name(name: &str) -> String {
+    let mut ident = String::new();
+
+    for word in identifier_words(name) {
+        if !ident.is_empty() {
+            ident.push('_');
+        }
+
+        let mut previous_lowercase = false;
+        for c in word.chars() {
+            if c.is_ascii_uppercase() && previous_lowercase {
+                ident.push('_');
+            }
+
+            previous_lowercase = c.is_ascii_lowercase() || c.is_ascii_digit();
+            ident.push(c.to_ascii_lowercase());
+        }
+    }
+
+    escape_identifier(ident)
+}
+
+// makes `ident` usable as a rust identifier. `self`, `Self`, `super` and `crate`
+// can't be raw identifiers, so they get a trailing `_` instead
+fn escape_identifier(mut ident: String) -> String {
+    if ident.is_empty() {
+        ident.push_str("unnamed");
+    } else if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+
+    match ident.as_str() {
+        "self" | "Self" | "super" | "crate" => format!("{}_", ident),
+        _ if is_keyword(&ident) => format!("r#{}", ident),
+        _ => ident
+    }
+}
+
+// `name`, or `name_2`, `name_3`... if that is already taken
+fn unique_name(name: String, taken: &mut HashSet<String>) -> String {
+    let mut unique = name.clone();
+    let mut suffix = 1;
+
+    while !taken.insert(unique.clone()) {
+        suffix += 1;
+        unique = format!("{}_{}", name, suffix);
+    }
+
+    unique
+}
+
+fn is_keyword(ident: &str) -> bool {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+        "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while", "abstract", "become",
+        "box", "do", "final", "gen", "macro", "override", "priv", "try", "typeof", "unsized", "virtual", "yield"
+    ];
+
+    KEYWORDS.contains(&ident)
+}
+
+// the children of an edge, filled in by the closure a generated edge function takes
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    predicates: Vec<Predicate>,
+    filter: Option<Condition>,
+    alias: Option<Alias>,
+    // the first invalid alias, reported by `edge` so the closure can keep chaining
+    error: Option<String>
+}
+
+impl Selection {
+    // fails on an invalid alias or a filter that never holds, like `EdgeBuilder::build`
+    pub fn edge(name: &str, select: impl FnOnce(&mut Selection)) -> Result<Predicate, String> {
+        let mut selection = Selection::default();
+        select(&mut selection);
+
+        if let Some(err) = selection.error {
+            return Err(err);
+        }
+
+        let mut builder = EdgeBuilder::default();
+        builder.name(name).predicates(selection.predicates);
+
+        if let Some(filter) = selection.filter {
+            builder.filter(filter);
+        }
+
+        if let Some(alias) = selection.alias {
+            builder.alias(alias);
+        }
+
+        builder.build().map(Predicate::Edge)
+    }
+
+    pub fn select(&mut self, predicate: Predicate) -> &mut Self {
+        self.predicates.push(predicate);
+        self
+    }
+
+    pub fn filter(&mut self, condition: Condition) -> &mut Self {
+        self.filter = Some(condition);
+        self
+    }
+
+    pub fn alias(&mut self, alias: &str) -> &mut Self {
+        match Alias::new(alias) {
+            Ok(alias) => self.alias = Some(alias),
+            Err(err) => {
+                self.error.get_or_insert(err.to_string());
+            }
+        }
+        self
+    }
+}
@@ -1,16 +1,108 @@
-use crate::ToQueryString;
-use serde_json::Value;
-use crate::predicate::Variable;
+use crate::{ToQueryString, PrettyConfig};
+use crate::printer::{write_quoted, write_separated};
+use crate::predicate::{UidVar, ValueVar, Variable};
+use crate::names::{Literal, PredicateName, Uid, VarName, escape_regex};
+use crate::queryblock::UidRef;
+use crate::validation::{SchemaCheck, VariableKind, VariableScope};
 use itertools::Itertools;
+use std::fmt::{self, Write};
+use std::hash::{Hash, Hasher};
+use std::ops::{BitAnd, BitOr, Not};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
-#[derive(Clone, Debug)]
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, FixedOffset, SecondsFormat, TimeZone};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ConditionValue {
     String(String),
     StringArr(Vec<String>),
     Regexp(String),
-    Literal(String),
-    NumArr(Vec<String>),
-    Val(Variable)
+    Literal(Literal),
+    NumArr(Vec<Literal>),
+    Val(ValueVar),
+    Param(VarName),
+    Float(FiniteFloat),
+    Bool(bool),
+    #[cfg(feature = "chrono")]
+    DateTime(DateTime<FixedOffset>),
+    Uid(Uid),
+    List(ValueList)
+}
+
+// DQL has no NaN or infinity, so floats are checked when they are wrapped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FiniteFloat(f64);
+
+impl FiniteFloat {
+    pub fn new(value: f64) -> Option<Self> {
+        match value.is_finite() {
+            true => Some(FiniteFloat(value)),
+            false => None
+        }
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+// the value is never NaN, so equality is reflexive; 0.0 and -0.0 hash alike as they're equal
+impl Eq for FiniteFloat {}
+
+impl Hash for FiniteFloat {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let value = if self.0 == 0.0 { 0.0f64 } else { self.0 };
+        value.to_bits().hash(state);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for FiniteFloat {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for FiniteFloat {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = f64::deserialize(deserializer)?;
+        FiniteFloat::new(value).ok_or_else(|| serde::de::Error::custom(format!("invalid float: {:?}", value)))
+    }
+}
+
+// the items of a `[...]` list, which can only be single values
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ValueList(Vec<ConditionValue>);
+
+impl ValueList {
+    pub fn new(values: Vec<ConditionValue>) -> Result<Self, String> {
+        match values.iter().find(|value| !value.is_scalar()) {
+            Some(nested) => Err(format!("invalid list item: {:?}", nested)),
+            None => Ok(ValueList(values))
+        }
+    }
+
+    pub fn values(&self) -> &[ConditionValue] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for ValueList {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for ValueList {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ValueList::new(Vec::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
 }
 
 impl ConditionValue {
@@ -22,12 +114,17 @@ impl ConditionValue {
         ConditionValue::Regexp(value.to_string())
     }
 
+    // panics on anything that isn't a number, date, uid or quoted string
     pub fn literal(value: &str) -> ConditionValue {
-        ConditionValue::Literal(value.to_string())
+        ConditionValue::Literal(value.into())
     }
 
     pub fn literal_int(value: i64) -> ConditionValue {
-        ConditionValue::Literal(format!("{}", value))
+        ConditionValue::Literal(format!("{}", value).into())
+    }
+
+    pub fn param(name: &str) -> ConditionValue {
+        ConditionValue::Param(name.into())
     }
 
     pub fn string_arr(value: Vec<&str>) -> ConditionValue {
@@ -35,66 +132,641 @@ impl ConditionValue {
     }
 
     pub fn num_arr(value: Vec<i64>) -> ConditionValue {
-        ConditionValue::NumArr(value.into_iter().map(|value| format!("{}", value)).collect())
+        ConditionValue::NumArr(value.into_iter().map(|value| format!("{}", value).into()).collect())
+    }
+
+    // panics on NaN and infinities, which DQL can't express
+    pub fn float(value: f64) -> ConditionValue {
+        match FiniteFloat::new(value) {
+            Some(value) => ConditionValue::Float(value),
+            None => panic!("Invalid float: {:?}", value)
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    pub fn datetime<Tz: TimeZone>(value: DateTime<Tz>) -> ConditionValue {
+        ConditionValue::DateTime(value.fixed_offset())
+    }
+
+    pub fn uid(value: &str) -> ConditionValue {
+        ConditionValue::Uid(value.into())
+    }
+
+    // panics on nested lists; use `ValueList::new` to handle the error instead
+    pub fn list(values: Vec<ConditionValue>) -> ConditionValue {
+        ConditionValue::List(ValueList::new(values).unwrap())
+    }
+
+    pub(crate) fn collect_variables(&self, scope: &mut VariableScope) {
+        match self {
+            ConditionValue::Val(variable) => scope.use_variable(variable.as_str(), VariableKind::Value),
+            ConditionValue::Param(name) => scope.use_param(name.as_str()),
+            ConditionValue::List(values) => values.values().iter().for_each(|value| value.collect_variables(scope)),
+            _ => ()
+        }
+    }
+
+    // values that can appear inside a list
+    fn is_scalar(&self) -> bool {
+        !matches!(self,
+            ConditionValue::StringArr(_) | ConditionValue::NumArr(_) | ConditionValue::List(_) | ConditionValue::Regexp(_))
+    }
+}
+
+impl From<&str> for ConditionValue {
+    fn from(value: &str) -> Self {
+        ConditionValue::string(value)
+    }
+}
+
+impl From<String> for ConditionValue {
+    fn from(value: String) -> Self {
+        ConditionValue::String(value)
+    }
+}
+
+impl From<i64> for ConditionValue {
+    fn from(value: i64) -> Self {
+        ConditionValue::literal_int(value)
+    }
+}
+
+impl From<i32> for ConditionValue {
+    fn from(value: i32) -> Self {
+        ConditionValue::literal_int(value.into())
+    }
+}
+
+impl From<u32> for ConditionValue {
+    fn from(value: u32) -> Self {
+        ConditionValue::literal_int(value.into())
+    }
+}
+
+impl From<f64> for ConditionValue {
+    fn from(value: f64) -> Self {
+        ConditionValue::float(value)
+    }
+}
+
+impl From<f32> for ConditionValue {
+    fn from(value: f32) -> Self {
+        ConditionValue::float(value.into())
+    }
+}
+
+impl From<bool> for ConditionValue {
+    fn from(value: bool) -> Self {
+        ConditionValue::Bool(value)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<Tz: TimeZone> From<DateTime<Tz>> for ConditionValue {
+    fn from(value: DateTime<Tz>) -> Self {
+        ConditionValue::datetime(value)
+    }
+}
+
+impl From<Vec<&str>> for ConditionValue {
+    fn from(value: Vec<&str>) -> Self {
+        ConditionValue::string_arr(value)
+    }
+}
+
+impl From<Vec<String>> for ConditionValue {
+    fn from(value: Vec<String>) -> Self {
+        ConditionValue::StringArr(value)
+    }
+}
+
+impl From<Vec<i64>> for ConditionValue {
+    fn from(value: Vec<i64>) -> Self {
+        ConditionValue::num_arr(value)
+    }
+}
+
+impl From<Vec<f64>> for ConditionValue {
+    fn from(value: Vec<f64>) -> Self {
+        ConditionValue::list(value.into_iter().map(ConditionValue::float).collect())
+    }
+}
+
+impl From<Vec<ConditionValue>> for ConditionValue {
+    fn from(value: Vec<ConditionValue>) -> Self {
+        ConditionValue::list(value)
+    }
+}
+
+impl From<ValueVar> for ConditionValue {
+    fn from(value: ValueVar) -> Self {
+        ConditionValue::Val(value)
+    }
+}
+
+impl From<&ValueVar> for ConditionValue {
+    fn from(value: &ValueVar) -> Self {
+        ConditionValue::Val(value.clone())
+    }
+}
+
+impl From<Variable> for ConditionValue {
+    fn from(value: Variable) -> Self {
+        ConditionValue::Val(value.var().clone())
     }
 }
 
 impl ToQueryString for ConditionValue {
-    fn to_query_string(&self) -> String {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
         match self {
-            ConditionValue::Regexp(value) | ConditionValue::Literal(value) => format!("{}", value),
-            ConditionValue::String(value) => Value::String(value.clone()).to_string(),
-            ConditionValue::Val(value) => format!("val({})", value.get_name()),
-            ConditionValue::StringArr(value) => format!("[{}]", value.iter().map(|item| Value::String(item.to_string()).to_string()).join(", ")),
-            ConditionValue::NumArr(value) => format!("[{}]", value.iter().join(", "))
+            ConditionValue::Regexp(value) => write!(w, "/{}/", escape_regex(value)),
+            ConditionValue::Literal(value) => value.write_query(w, config, depth),
+            ConditionValue::String(value) => write_quoted(w, value),
+            ConditionValue::Val(value) => write!(w, "val({})", value.as_str()),
+            ConditionValue::Param(name) => {
+                w.write_char('$')?;
+                name.write_query(w, config, depth)
+            },
+            ConditionValue::StringArr(value) => {
+                w.write_char('[')?;
+                write_separated(w, ", ", value, |w, item| write_quoted(w, item))?;
+                w.write_char(']')
+            },
+            ConditionValue::NumArr(value) => {
+                w.write_char('[')?;
+                write_separated(w, ", ", value, |w, item| item.write_query(w, config, depth))?;
+                w.write_char(']')
+            },
+            ConditionValue::Float(value) => write!(w, "{}", value.get()),
+            ConditionValue::Bool(value) => write!(w, "{}", value),
+            #[cfg(feature = "chrono")]
+            ConditionValue::DateTime(value) => write_quoted(w, &value.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            ConditionValue::Uid(value) => value.write_query(w, config, depth),
+            ConditionValue::List(values) => {
+                w.write_char('[')?;
+                write_separated(w, ", ", values.values(), |w, value| value.write_query(w, config, depth))?;
+                w.write_char(']')
+            }
         }
     }
 }
 
-#[derive(Clone, Debug)]
+// the left hand side of a comparison
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ConditionSubject {
+    Predicate(PredicateName),
+    Count(PredicateName),
+    Len(UidVar),
+    Val(ValueVar)
+}
+
+impl ConditionSubject {
+    pub fn predicate(name: &str) -> ConditionSubject {
+        ConditionSubject::Predicate(name.into())
+    }
+
+    pub fn count(name: &str) -> ConditionSubject {
+        ConditionSubject::Count(name.into())
+    }
+
+    pub fn len(variable: impl Into<UidVar>) -> ConditionSubject {
+        ConditionSubject::Len(variable.into())
+    }
+
+    pub fn val(variable: impl Into<ValueVar>) -> ConditionSubject {
+        ConditionSubject::Val(variable.into())
+    }
+
+    pub fn eq(self, value: impl Into<ConditionValue>) -> Condition {
+        Condition::EQ(self, value.into())
+    }
+
+    pub fn ge(self, value: impl Into<ConditionValue>) -> Condition {
+        Condition::GE(self, value.into())
+    }
+
+    pub fn gt(self, value: impl Into<ConditionValue>) -> Condition {
+        Condition::GT(self, value.into())
+    }
+
+    pub fn le(self, value: impl Into<ConditionValue>) -> Condition {
+        Condition::LE(self, value.into())
+    }
+
+    pub fn lt(self, value: impl Into<ConditionValue>) -> Condition {
+        Condition::LT(self, value.into())
+    }
+
+    fn check_schema(&self, function: &str, check: &mut SchemaCheck, root: bool) {
+        match self {
+            ConditionSubject::Predicate(predicate) => check.function(function, predicate, root),
+            ConditionSubject::Count(predicate) => check.count(predicate, root),
+            ConditionSubject::Len(_) | ConditionSubject::Val(_) => ()
+        }
+    }
+}
+
+impl From<PredicateName> for ConditionSubject {
+    fn from(name: PredicateName) -> Self {
+        ConditionSubject::Predicate(name)
+    }
+}
+
+impl From<&str> for ConditionSubject {
+    fn from(name: &str) -> Self {
+        ConditionSubject::Predicate(name.into())
+    }
+}
+
+impl From<String> for ConditionSubject {
+    fn from(name: String) -> Self {
+        ConditionSubject::Predicate(name.into())
+    }
+}
+
+impl ToQueryString for ConditionSubject {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        match self {
+            ConditionSubject::Predicate(name) => name.write_query(w, config, depth),
+            ConditionSubject::Count(name) => {
+                w.write_str("count(")?;
+                name.write_query(w, config, depth)?;
+                w.write_char(')')
+            },
+            ConditionSubject::Len(variable) => write!(w, "len({})", variable.as_str()),
+            ConditionSubject::Val(variable) => write!(w, "val({})", variable.as_str())
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(remote = "Self"))]
 pub enum Condition {
-    UID(String),
-    EQ(String, ConditionValue),
-    GE(String, ConditionValue),
-    GT(String, ConditionValue),
-    LE(String, ConditionValue),
-    LT(String, ConditionValue),
-    HAS(String),
-    REGEXP(String, ConditionValue),
+    UID(Vec<UidRef>),
+    EQ(ConditionSubject, ConditionValue),
+    GE(ConditionSubject, ConditionValue),
+    GT(ConditionSubject, ConditionValue),
+    LE(ConditionSubject, ConditionValue),
+    LT(ConditionSubject, ConditionValue),
+    HAS(PredicateName),
+    REGEXP(PredicateName, ConditionValue),
     AND(Box<Condition>, Box<Condition>),
     OR(Box<Condition>, Box<Condition>),
     NOT(Box<Condition>),
+    ALL(Vec<Condition>),
+    ANY(Vec<Condition>),
 }
 
 impl Condition {
-    pub fn len(value: &str) -> String {
-        format!("len({})", value)
+    // an empty conjunction is always true and an empty disjunction never is
+    pub const TRUE: Condition = Condition::ALL(Vec::new());
+    pub const FALSE: Condition = Condition::ANY(Vec::new());
+
+    pub fn len(value: impl Into<UidVar>) -> ConditionSubject {
+        ConditionSubject::len(value)
     }
 
     pub fn has(value: &str) -> Condition {
-        Condition::HAS(value.to_string())
+        Condition::HAS(value.into())
     }
 
+    // a comma separated list of uids and variables; panics on anything else
     pub fn uid(value: &str) -> Condition {
-        Condition::UID(value.to_string())
+        Condition::UID(value.split(',').map(|item| item.trim().into()).collect())
+    }
+
+    pub fn uid_in(variables: &[&UidVar]) -> Condition {
+        Condition::UID(variables.iter().map(|&variable| variable.into()).collect())
+    }
+
+    // None when there is nothing to combine
+    pub fn and_all(conditions: impl IntoIterator<Item = Condition>) -> Option<Condition> {
+        conditions.into_iter().fold1(|left, right| left & right)
+    }
+
+    pub fn or_any(conditions: impl IntoIterator<Item = Condition>) -> Option<Condition> {
+        conditions.into_iter().fold1(|left, right| left | right)
     }
+
+    // `root` is set for the `func:` of a query block, where comparisons need an index too
+    pub(crate) fn check_schema(&self, check: &mut SchemaCheck, root: bool) {
+        match self {
+            Condition::UID(_) => (),
+            Condition::EQ(subject, _) => subject.check_schema("eq", check, root),
+            Condition::GE(subject, _) => subject.check_schema("ge", check, root),
+            Condition::GT(subject, _) => subject.check_schema("gt", check, root),
+            Condition::LE(subject, _) => subject.check_schema("le", check, root),
+            Condition::LT(subject, _) => subject.check_schema("lt", check, root),
+            Condition::HAS(predicate) => check.predicate(predicate),
+            Condition::REGEXP(predicate, _) => check.function("regexp", predicate, root),
+            Condition::AND(left, right) | Condition::OR(left, right) => {
+                left.check_schema(check, root);
+                right.check_schema(check, root);
+            },
+            Condition::NOT(condition) => condition.check_schema(check, root),
+            Condition::ALL(conditions) | Condition::ANY(conditions) => {
+                conditions.iter().for_each(|condition| condition.check_schema(check, root));
+            }
+        }
+    }
+
+    pub(crate) fn collect_variables(&self, scope: &mut VariableScope) {
+        match self {
+            Condition::UID(refs) => refs.iter().for_each(|uid| {
+                if let UidRef::Var(variable) = uid {
+                    scope.use_variable(variable.as_str(), VariableKind::Uid);
+                }
+            }),
+            Condition::EQ(subject, value)
+            | Condition::GE(subject, value)
+            | Condition::GT(subject, value)
+            | Condition::LE(subject, value)
+            | Condition::LT(subject, value) => {
+                match subject {
+                    ConditionSubject::Len(variable) => scope.use_variable(variable.as_str(), VariableKind::Uid),
+                    ConditionSubject::Val(variable) => scope.use_variable(variable.as_str(), VariableKind::Value),
+                    ConditionSubject::Predicate(_) | ConditionSubject::Count(_) => ()
+                }
+
+                value.collect_variables(scope);
+            },
+            Condition::HAS(_) => (),
+            Condition::REGEXP(_, value) => value.collect_variables(scope),
+            Condition::AND(left, right) | Condition::OR(left, right) => {
+                left.collect_variables(scope);
+                right.collect_variables(scope);
+            },
+            Condition::NOT(condition) => condition.collect_variables(scope),
+            Condition::ALL(conditions) | Condition::ANY(conditions) => {
+                conditions.iter().for_each(|condition| condition.collect_variables(scope));
+            }
+        }
+    }
+
+    // whether the condition holds for every node (`Some(true)`) or for none (`Some(false)`)
+    // because of the constants in it, e.g. `has(a) | TRUE` or `!TRUE`
+    pub fn constant(&self) -> Option<bool> {
+        if let Condition::NOT(condition) = self {
+            return condition.constant().map(|value| !value);
+        }
+
+        let (operator, operands) = self.connective()?;
+        let conjunction = operator == " AND ";
+        let constants = operands.iter().map(|operand| operand.constant()).collect::<Vec<_>>();
+
+        if constants.contains(&Some(!conjunction)) {
+            Some(!conjunction)
+        } else if constants.iter().all(|constant| *constant == Some(conjunction)) {
+            Some(conjunction)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_true(&self) -> bool {
+        self.constant() == Some(true)
+    }
+
+    pub fn is_false(&self) -> bool {
+        self.constant() == Some(false)
+    }
+
+    // the first function the condition applies, if it has one
+    fn first_function(&self) -> Option<&Condition> {
+        match self {
+            Condition::NOT(condition) => condition.first_function(),
+            Condition::AND(left, right) | Condition::OR(left, right) => left.first_function().or_else(|| right.first_function()),
+            Condition::ALL(conditions) | Condition::ANY(conditions) => conditions.iter().find_map(Condition::first_function),
+            condition => Some(condition)
+        }
+    }
+
+    // flattens nested AND/OR into ALL/ANY, removes double negation and
+    // duplicate clauses and folds the TRUE/FALSE constants away
+    pub fn simplify(self) -> Condition {
+        match self {
+            Condition::NOT(condition) => match condition.simplify() {
+                Condition::NOT(inner) => *inner,
+                inner if inner.is_true() => Condition::FALSE,
+                inner if inner.is_false() => Condition::TRUE,
+                inner => Condition::NOT(Box::new(inner))
+            },
+            Condition::AND(..) | Condition::ALL(_) => self.simplify_connective(true),
+            Condition::OR(..) | Condition::ANY(_) => self.simplify_connective(false),
+            condition => condition
+        }
+    }
+
+    fn simplify_connective(self, conjunction: bool) -> Condition {
+        let absorbing = if conjunction { Condition::FALSE } else { Condition::TRUE };
+        let operands = match self.connective() {
+            Some((_, operands)) => operands.into_iter().cloned().collect::<Vec<_>>(),
+            None => return self
+        };
+
+        let mut simplified = vec![];
+
+        for operand in operands.into_iter().map(Condition::simplify) {
+            if operand == absorbing {
+                return absorbing;
+            }
+
+            // simplifying can turn an operand into a chain of the same operator
+            match operand {
+                Condition::ALL(nested) if conjunction => simplified.extend(nested),
+                Condition::ANY(nested) if !conjunction => simplified.extend(nested),
+                operand => simplified.push(operand)
+            }
+        }
+
+        let mut simplified = simplified.into_iter().unique().collect::<Vec<_>>();
+
+        match (simplified.len(), conjunction) {
+            (1, _) => simplified.remove(0),
+            (_, true) => Condition::ALL(simplified),
+            (_, false) => Condition::ANY(simplified)
+        }
+    }
+
+    // the operator and operands of an AND/OR chain, with nested chains of the
+    // same operator flattened so they render without extra parentheses
+    fn connective(&self) -> Option<(&'static str, Vec<&Condition>)> {
+        fn collect<'a>(condition: &'a Condition, conjunction: bool, operands: &mut Vec<&'a Condition>) {
+            match condition {
+                Condition::AND(left, right) if conjunction => {
+                    collect(left, conjunction, operands);
+                    collect(right, conjunction, operands);
+                },
+                Condition::OR(left, right) if !conjunction => {
+                    collect(left, conjunction, operands);
+                    collect(right, conjunction, operands);
+                },
+                Condition::ALL(conditions) if conjunction => conditions.iter()
+                    .for_each(|condition| collect(condition, conjunction, operands)),
+                Condition::ANY(conditions) if !conjunction => conditions.iter()
+                    .for_each(|condition| collect(condition, conjunction, operands)),
+                condition => operands.push(condition)
+            }
+        }
+
+        let (operator, conjunction) = match self {
+            Condition::AND(..) | Condition::ALL(_) => (" AND ", true),
+            Condition::OR(..) | Condition::ANY(_) => (" OR ", false),
+            _ => return None
+        };
+
+        let mut operands = vec![];
+        collect(self, conjunction, &mut operands);
+        Some((operator, operands))
+    }
+
+    // DQL has no constants, so a condition that always (or never) holds is written as
+    // one of its functions OR (AND) its negation. one without any function is written
+    // as `true` or `false`, which only happens when it is rendered on its own: query
+    // blocks leave out filters that always hold and reject ones that never do
+    fn write_constant(&self, value: bool, w: &mut impl Write, config: &PrettyConfig, depth: usize, parenthesize: bool) -> fmt::Result {
+        let function = match self.first_function() {
+            Some(function) => function,
+            None => return write!(w, "{}", value)
+        };
+
+        if parenthesize {
+            w.write_char('(')?;
+        }
+
+        function.write_query(w, config, depth)?;
+        w.write_str(if value { " OR not " } else { " AND not " })?;
+        function.write_query(w, config, depth)?;
+
+        if parenthesize {
+            w.write_char(')')?;
+        }
+
+        Ok(())
+    }
+
+    fn write_condition(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize, parenthesize: bool) -> fmt::Result {
+        if let Some(value) = self.constant() {
+            return self.write_constant(value, w, config, depth, parenthesize);
+        }
+
+        // the condition isn't constant, so none of its operands decides it and the
+        // constant ones can be left out
+        let connective = self.connective().map(|(operator, operands)| {
+            let neutral = Some(operator == " AND ");
+            (operator, operands.into_iter().filter(|operand| operand.constant() != neutral).collect::<Vec<_>>())
+        });
+
+        match connective {
+            Some((_, operands)) if operands.len() == 1 => operands[0].write_condition(w, config, depth, parenthesize),
+            Some((operator, operands)) => {
+                if parenthesize {
+                    w.write_char('(')?;
+                }
+
+                write_separated(w, operator, operands, |w, operand| operand.write_condition(w, config, depth, true))?;
+
+                if parenthesize {
+                    w.write_char(')')?;
+                }
+
+                Ok(())
+            },
+            None => self.write_query(w, config, depth)
+        }
+    }
+
+    // writes ` @filter(...)`, or nothing for a filter that always holds since it
+    // doesn't need rendering
+    pub(crate) fn write_filter(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        if self.is_true() {
+            return Ok(());
+        }
+
+        w.write_str(" @filter(")?;
+        self.write_query(w, config, depth)?;
+        w.write_char(')')
+    }
+}
+
+impl BitAnd for Condition {
+    type Output = Condition;
+
+    fn bitand(self, other: Condition) -> Condition {
+        Condition::AND(Box::new(self), Box::new(other))
+    }
+}
+
+impl BitOr for Condition {
+    type Output = Condition;
+
+    fn bitor(self, other: Condition) -> Condition {
+        Condition::OR(Box::new(self), Box::new(other))
+    }
+}
+
+impl Not for Condition {
+    type Output = Condition;
+
+    fn not(self) -> Condition {
+        Condition::NOT(Box::new(self))
+    }
+}
+
+// a filter that never holds can't match anything, which is almost certainly a
+// mistake and, without a function in it, can't be written in DQL at all
+pub(crate) fn validate_filter(filter: &Condition) -> Result<(), String> {
+    match filter.is_false() {
+        true => Err(format!("the filter never holds: {:?}", filter)),
+        false => Ok(())
+    }
+}
+
+fn write_function(w: &mut impl Write, function: &str, subject: &impl ToQueryString, value: Option<&ConditionValue>, config: &PrettyConfig, depth: usize) -> fmt::Result {
+    write!(w, "{}(", function)?;
+    subject.write_query(w, config, depth)?;
+
+    if let Some(value) = value {
+        w.write_str(", ")?;
+        value.write_query(w, config, depth)?;
+    }
+
+    w.write_char(')')
 }
 
+// regexp renders any value, but dgraph only accepts a `/pattern/` there
+#[cfg(feature = "serde")]
+impl_checked_serde!(Condition, |condition: &Condition| match condition {
+    Condition::REGEXP(_, value) if !matches!(value, ConditionValue::Regexp(_)) => {
+        Err(format!("regexp needs a regexp value: {:?}", value))
+    },
+    _ => Ok(())
+});
+
 impl ToQueryString for Condition {
-    fn to_query_string(&self) -> String {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
         match self {
-            Condition::UID(id) => format!("uid({id})", id = id),
-            Condition::EQ(predicate, value) => format!("eq({predicate}, {value})", predicate = predicate, value = value.to_query_string()),
-            Condition::GE(predicate, value) => format!("ge({predicate}, {value})", predicate = predicate, value = value.to_query_string()),
-            Condition::GT(predicate, value) => format!("gt({predicate}, {value})", predicate = predicate, value = value.to_query_string()),
-            Condition::LE(predicate, value) => format!("le({predicate}, {value})", predicate = predicate, value = value.to_query_string()),
-            Condition::LT(predicate, value) => format!("lt({predicate}, {value})", predicate = predicate, value = value.to_query_string()),
-            Condition::HAS(predicate) => format!("has({predicate})", predicate = predicate),
-            Condition::REGEXP(predicate, ConditionValue::Regexp(regex_val)) => format!("regexp({predicate}, /{regex}/)", predicate = predicate, regex = regex_val),
-            Condition::AND(left, right) => format!("({left} AND {right})", left = left.to_query_string(), right = right.to_query_string()),
-            Condition::OR(left, right) => format!("({left} OR {right})", left = left.to_query_string(), right = right.to_query_string()),
-            Condition::NOT(condition) => format!("(not {condition})", condition = condition.to_query_string()),
-            _ => panic!("Invalid condition: {:?}", self)
-        }
-    }
-}
\ No newline at end of file
+            Condition::UID(refs) => {
+                w.write_str("uid(")?;
+                write_separated(w, ", ", refs, |w, uid| uid.write_query(w, config, depth))?;
+                w.write_char(')')
+            },
+            Condition::EQ(predicate, value) => write_function(w, "eq", predicate, Some(value), config, depth),
+            Condition::GE(predicate, value) => write_function(w, "ge", predicate, Some(value), config, depth),
+            Condition::GT(predicate, value) => write_function(w, "gt", predicate, Some(value), config, depth),
+            Condition::LE(predicate, value) => write_function(w, "le", predicate, Some(value), config, depth),
+            Condition::LT(predicate, value) => write_function(w, "lt", predicate, Some(value), config, depth),
+            Condition::HAS(predicate) => write_function(w, "has", predicate, None, config, depth),
+            Condition::REGEXP(predicate, value) => write_function(w, "regexp", predicate, Some(value), config, depth),
+            Condition::AND(..) | Condition::OR(..) | Condition::ALL(_) | Condition::ANY(_) => {
+                self.write_condition(w, config, depth, false)
+            },
+            Condition::NOT(_) if self.constant().is_some() => self.write_condition(w, config, depth, false),
+            Condition::NOT(condition) => {
+                w.write_str("not ")?;
+                condition.write_condition(w, config, depth, true)
+            }
+        }
+    }
+}
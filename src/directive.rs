@@ -0,0 +1,50 @@
+use crate::{ToQueryString, PrettyConfig};
+use crate::names::PredicateName;
+use crate::printer::write_separated;
+use std::fmt::{self, Write};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+// directives other than `@filter`, which takes a condition and is kept separately
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Directive {
+    // an empty list cascades over every predicate in the selection
+    Cascade(Vec<PredicateName>),
+    Normalize,
+    IgnoreReflex
+}
+
+impl Directive {
+    pub fn cascade() -> Directive {
+        Directive::Cascade(vec![])
+    }
+
+    pub fn cascade_on(predicates: &[&str]) -> Directive {
+        Directive::Cascade(predicates.iter().map(|&name| name.into()).collect())
+    }
+}
+
+impl ToQueryString for Directive {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        match self {
+            Directive::Cascade(predicates) if predicates.is_empty() => w.write_str("@cascade"),
+            Directive::Cascade(predicates) => {
+                w.write_str("@cascade(")?;
+                write_separated(w, ", ", predicates, |w, name| name.write_query(w, config, depth))?;
+                w.write_char(')')
+            },
+            Directive::Normalize => w.write_str("@normalize"),
+            Directive::IgnoreReflex => w.write_str("@ignorereflex")
+        }
+    }
+}
+
+pub(crate) fn write_directives(w: &mut impl Write, directives: &[Directive], config: &PrettyConfig, depth: usize) -> fmt::Result {
+    for directive in directives {
+        w.write_char(' ')?;
+        directive.write_query(w, config, depth)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use crate::condition::{Condition, ConditionSubject, ConditionValue};
+use crate::names::PredicateName;
+use crate::predicate::{UidVar, ValueVar};
+
+// entry points for building filters, e.g.
+// `pred("age").ge(18) & count("friend").gt(5) | !has("banned")`
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Pred {
+    name: PredicateName
+}
+
+pub fn pred(name: impl Into<PredicateName>) -> Pred {
+    Pred {
+        name: name.into()
+    }
+}
+
+pub fn has(name: impl Into<PredicateName>) -> Condition {
+    Condition::HAS(name.into())
+}
+
+pub fn uid(value: &str) -> Condition {
+    Condition::uid(value)
+}
+
+pub fn count(name: &str) -> ConditionSubject {
+    ConditionSubject::count(name)
+}
+
+pub fn len(variable: impl Into<UidVar>) -> ConditionSubject {
+    ConditionSubject::len(variable)
+}
+
+pub fn val(variable: impl Into<ValueVar>) -> ConditionSubject {
+    ConditionSubject::val(variable)
+}
+
+impl Pred {
+    pub fn eq(self, value: impl Into<ConditionValue>) -> Condition {
+        ConditionSubject::Predicate(self.name).eq(value)
+    }
+
+    pub fn ge(self, value: impl Into<ConditionValue>) -> Condition {
+        ConditionSubject::Predicate(self.name).ge(value)
+    }
+
+    pub fn gt(self, value: impl Into<ConditionValue>) -> Condition {
+        ConditionSubject::Predicate(self.name).gt(value)
+    }
+
+    pub fn le(self, value: impl Into<ConditionValue>) -> Condition {
+        ConditionSubject::Predicate(self.name).le(value)
+    }
+
+    pub fn lt(self, value: impl Into<ConditionValue>) -> Condition {
+        ConditionSubject::Predicate(self.name).lt(value)
+    }
+
+    pub fn regexp(self, pattern: &str) -> Condition {
+        Condition::REGEXP(self.name, ConditionValue::regexp(pattern))
+    }
+
+    pub fn has(self) -> Condition {
+        Condition::HAS(self.name)
+    }
+}
@@ -1,3 +1,29 @@
+// `serde(remote = "Self")` turns the derived (de)serialize into inherent fns; these
+// impls forward to them and run `$check` on every deserialized value, so serde can't
+// produce a value the type's constructors or builder would have refused
+#[cfg(feature = "serde")]
+macro_rules! impl_checked_serde {
+    ($name:ty, $check:expr) => {
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                <$name>::serialize(self, serializer)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = <$name>::deserialize(deserializer)?;
+                $check(&value).map_err(serde::de::Error::custom)?;
+                Ok(value)
+            }
+        }
+    };
+}
+
+// lets the tests compile generated code, which refers to the crate by name
+#[cfg(test)]
+extern crate self as dgraph_query_lib;
+#[cfg(test)]
 mod tests;
 pub mod query;
 pub mod queryblock;
@@ -6,30 +32,91 @@ pub mod predicate;
 pub mod upsert;
 pub mod mutation;
 pub mod schema;
+pub mod response;
+pub mod names;
+pub mod parser;
+pub mod printer;
+pub mod dsl;
+pub mod directive;
+pub mod validation;
+pub mod codegen;
 
 pub use query::QueryBuilder;
 pub use queryblock::QueryBlockBuilder;
 pub use predicate::EdgeBuilder;
 pub use mutation::MutationBuilder;
+pub use response::{Response, BlockHandle};
+pub use printer::PrettyConfig;
 
-pub trait ToQueryString {
-    fn to_query_string(&self) -> String;
-}
+use printer::IoWriter;
+use std::{fmt, io};
 
-trait IndentedString {
-    fn indent(&self) -> String;
+macro_rules! impl_display {
+    ($($name:ty),*) => {
+        $(
+            impl fmt::Display for $name {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    self.write_query(f, &PrettyConfig::default(), 0)
+                }
+            }
+        )*
+    };
 }
 
-impl IndentedString for String {
-    fn indent(&self) -> String {
-        format!("\t{}", self.replace("\n", "\n\t"))
+impl_display!(
+    query::Query, query::QueryParam, query::ParamType,
+    queryblock::QueryBlock, queryblock::QueryBlockType, queryblock::RootFunction, queryblock::UidRef,
+    predicate::Predicate, predicate::Edge, predicate::Field, predicate::Expand, predicate::ExpandTarget, predicate::UidVar, predicate::ValueVar, directive::Directive,
+    condition::Condition, condition::ConditionSubject, condition::ConditionValue,
+    names::PredicateName, names::VarName, names::Alias, names::TypeName, names::Uid, names::Literal
+);
+
+pub trait ToQueryString {
+    // `depth` is the nesting level the output starts at, which multi-line output indents relative to
+    fn write_query(&self, w: &mut impl fmt::Write, config: &PrettyConfig, depth: usize) -> fmt::Result;
+
+    fn to_query_string(&self) -> String {
+        self.to_query_string_with(&PrettyConfig::default())
+    }
+
+    fn to_query_string_with(&self, config: &PrettyConfig) -> String {
+        let mut rendered = String::new();
+        self.write_query_with(&mut rendered, config)
+            .expect("writing to a String can't fail");
+
+        rendered
+    }
+
+    fn write_query_with(&self, w: &mut impl fmt::Write, config: &PrettyConfig) -> fmt::Result {
+        self.write_query(w, config, 0)?;
+
+        if config.trailing_newline {
+            w.write_char('\n')?;
+        }
+
+        Ok(())
+    }
+
+    // rendering issues lots of small writes, so they're buffered here rather than
+    // sent straight to `w`
+    fn write_query_io(&self, w: &mut impl io::Write, config: &PrettyConfig) -> io::Result<()> {
+        let mut buffered = io::BufWriter::new(w);
+        let mut writer = IoWriter::new(&mut buffered);
+
+        self.write_query_with(&mut writer, config).map_err(|_| {
+            writer.error.take()
+                .unwrap_or_else(|| io::Error::other("formatter error"))
+        })?;
+
+        io::Write::flush(&mut buffered)
     }
 }
 
-impl<I: ToQueryString + Clone> ToQueryString for Option<I> {
-    fn to_query_string(&self) -> String {
-        self.clone()
-            .map(|item| item.to_query_string())
-            .unwrap_or("".to_string())
+impl<I: ToQueryString> ToQueryString for Option<I> {
+    fn write_query(&self, w: &mut impl fmt::Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        match self {
+            Some(item) => item.write_query(w, config, depth),
+            None => Ok(())
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,12 +1,17 @@
 use derive_builder::Builder;
-use crate::ToQueryString;
+use crate::PrettyConfig;
+use crate::names::{is_uid_literal, is_valid_identifier, next_default_name, NameGenerator, PredicateName};
+use crate::printer::write_quoted;
+use crate::predicate::UidVar;
+use crate::schema::{PredicateType, Schema};
+use crate::validation::{SchemaError, VariableKind, VariableScope};
 use itertools::Itertools;
-use crate::condition::{Condition, ConditionValue};
-use rand::random;
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Write};
 use serde::Serialize;
 
-#[derive(Builder, Serialize, Clone)]
+#[derive(Builder, Serialize, Clone, Debug, PartialEq)]
 pub struct Mutation {
     #[builder(default)]
     pub set: Vec<MutationUnit>,
@@ -14,7 +19,7 @@ pub struct Mutation {
     pub delete: Vec<MutationUnit>
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct MutationUnit {
     #[serde(flatten)]
     uid: MutationUID,
@@ -22,6 +27,146 @@ pub struct MutationUnit {
     predicates: HashMap<String, MutationPredicateValue>,
 }
 
+// why a mutation can't be written as RDF, where names and uids are spliced in as they are
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RdfError {
+    InvalidPredicate(String),
+    InvalidUid(String),
+    NullInSet(String),
+    NonFiniteFloat(String)
+}
+
+impl fmt::Display for RdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RdfError::InvalidPredicate(name) => write!(f, "`{}` can't be written as an RDF predicate", name),
+            RdfError::InvalidUid(uid) => write!(f, "`{}` is not a uid, blank node or uid variable", uid),
+            RdfError::NullInSet(name) => write!(f, "`{}` is set to null, which only a delete can do", name),
+            RdfError::NonFiniteFloat(name) => write!(f, "`{}` is given a NaN or infinite float, which RDF can't hold", name)
+        }
+    }
+}
+
+impl Error for RdfError {}
+
+// `name@en` is written as `<name>` with the tag moved onto the string it applies to
+fn split_lang(name: &str) -> (&str, Option<&str>) {
+    match name.find('@') {
+        Some(index) => (&name[..index], Some(&name[index + 1..])),
+        None => (name, None)
+    }
+}
+
+fn is_valid_lang(lang: &str) -> bool {
+    let mut subtags = lang.split('-');
+    let primary = subtags.next().unwrap_or_default();
+
+    !primary.is_empty()
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| !subtag.is_empty() && subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+// what a delete removes. in JSON a node with nothing but a uid deletes all of
+// its predicates and a `null` value deletes every value of the predicate; in
+// RDF both are spelled with `*`
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeleteOp {
+    Node(MutationUID),
+    Predicate(MutationUID, String),
+    Edge(MutationUID, String, MutationUID),
+    Value(MutationUID, String, MutationPredicateValue)
+}
+
+impl From<DeleteOp> for MutationUnit {
+    fn from(op: DeleteOp) -> Self {
+        match op {
+            DeleteOp::Node(uid) => MutationUnit::new(uid),
+            DeleteOp::Predicate(uid, name) => MutationUnit::new(uid).predicate(&name, MutationPredicateValue::Null),
+            DeleteOp::Edge(uid, name, target) => MutationUnit::new(uid).predicate(&name, MutationPredicateValue::Edge(target)),
+            DeleteOp::Value(uid, name, value) => MutationUnit::new(uid).predicate(&name, value)
+        }
+    }
+}
+
+impl MutationBuilder {
+    pub fn delete_op(&mut self, op: DeleteOp) -> &mut Self {
+        self.delete.get_or_insert_with(Vec::new).push(op.into());
+        self
+    }
+
+    pub fn delete_node(&mut self, uid: MutationUID) -> &mut Self {
+        self.delete_op(DeleteOp::Node(uid))
+    }
+
+    pub fn delete_predicate(&mut self, uid: MutationUID, name: &str) -> &mut Self {
+        self.delete_op(DeleteOp::Predicate(uid, name.to_string()))
+    }
+
+    pub fn delete_edge(&mut self, uid: MutationUID, name: &str, target: MutationUID) -> &mut Self {
+        self.delete_op(DeleteOp::Edge(uid, name.to_string(), target))
+    }
+
+    pub fn delete_value(&mut self, uid: MutationUID, name: &str, value: MutationPredicateValue) -> &mut Self {
+        self.delete_op(DeleteOp::Value(uid, name.to_string(), value))
+    }
+}
+
+impl Mutation {
+    // the RDF form, `{ set { ... } delete { ... } }`. names and uids are spliced in as
+    // they are, so anything `check_rdf` rejects is returned as the error instead
+    pub fn to_rdf(&self) -> Result<String, RdfError> {
+        self.to_rdf_with(&PrettyConfig::default())
+    }
+
+    pub fn to_rdf_with(&self, config: &PrettyConfig) -> Result<String, RdfError> {
+        self.check_rdf()?;
+
+        let mut rendered = String::new();
+        self.write_rdf(&mut rendered, config, 0)
+            .expect("writing to a String can't fail");
+
+        if config.trailing_newline {
+            rendered.push('\n');
+        }
+
+        Ok(rendered)
+    }
+
+    pub fn check_rdf(&self) -> Result<(), RdfError> {
+        let flattened = self.flatten();
+
+        flattened.set.iter().try_for_each(|unit| unit.check_rdf(false))?;
+        flattened.delete.iter().try_for_each(|unit| unit.check_rdf(true))
+    }
+
+    pub fn flatten(&self) -> Mutation {
+        Mutation {
+            set: self.set.iter().flat_map(MutationUnit::flatten).collect(),
+            delete: self.delete.iter().flat_map(MutationUnit::flatten).collect()
+        }
+    }
+
+    // reports unknown predicates, values that don't fit the predicate's type
+    // and new nodes that are created without a type
+    pub fn validate_against(&self, schema: &Schema) -> Result<(), Vec<SchemaError>> {
+        let mut errors = vec![];
+        let flattened = self.flatten();
+
+        flattened.set.iter().for_each(|unit| unit.check_schema(schema, true, &mut errors));
+        flattened.delete.iter().for_each(|unit| unit.check_schema(schema, false, &mut errors));
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors)
+        }
+    }
+
+    pub(crate) fn collect_variables(&self, scope: &mut VariableScope) {
+        let flattened = self.flatten();
+        flattened.set.iter().chain(&flattened.delete).for_each(|unit| unit.collect_variables(scope));
+    }
+}
+
 impl MutationUnit {
     pub fn new(uid: MutationUID) -> Self {
         Self {
@@ -38,18 +183,161 @@ impl MutationUnit {
     pub fn predicate_ref(&mut self, name: &str, value: MutationPredicateValue) {
         self.predicates.insert(name.to_string(), value);
     }
+
+    pub fn get_uid(&self) -> &MutationUID {
+        &self.uid
+    }
+
+    // pulls nested nodes out into units of their own, leaving edges to them
+    // behind. a nested node with nothing but a uid only stands for the edge
+    pub fn flatten(&self) -> Vec<MutationUnit> {
+        let mut units = vec![];
+        self.flatten_into(&mut units);
+        units
+    }
+
+    fn flatten_into(&self, units: &mut Vec<MutationUnit>) {
+        let mut unit = MutationUnit::new(self.uid.clone());
+        let mut nested = vec![];
+
+        for (name, value) in self.predicates.iter().sorted_by_key(|(name, _)| *name) {
+            let value = match value {
+                MutationPredicateValue::Node(node) => {
+                    nested.push(node);
+                    MutationPredicateValue::Edge(node.uid.clone())
+                },
+                MutationPredicateValue::Nodes(nodes) => {
+                    nested.extend(nodes);
+                    MutationPredicateValue::Edges(nodes.iter().map(|node| node.uid.clone()).collect())
+                },
+                value => value.clone()
+            };
+
+            unit.predicates.insert(name.clone(), value);
+        }
+
+        units.push(unit);
+        nested.into_iter()
+            .filter(|node| !node.predicates.is_empty())
+            .for_each(|node| node.flatten_into(units));
+    }
+
+    fn check_rdf(&self, delete: bool) -> Result<(), RdfError> {
+        self.uid.check_rdf()?;
+
+        for (name, value) in self.predicates.iter().sorted_by_key(|(name, _)| *name) {
+            let (predicate, lang) = split_lang(name);
+
+            // reverse edges can't be set directly, and only strings can carry a language
+            let valid_lang = match lang {
+                Some(lang) => is_valid_lang(lang) && value.is_string(),
+                None => true
+            };
+            if predicate.starts_with('~') || PredicateName::new(predicate).is_err() || !valid_lang {
+                return Err(RdfError::InvalidPredicate(name.clone()));
+            }
+
+            match value {
+                MutationPredicateValue::Edge(uid) => uid.check_rdf()?,
+                MutationPredicateValue::Edges(uids) => uids.iter().try_for_each(MutationUID::check_rdf)?,
+                MutationPredicateValue::Null if !delete => return Err(RdfError::NullInSet(name.clone())),
+                value if !value.is_finite() => return Err(RdfError::NonFiniteFloat(name.clone())),
+                _ => ()
+            }
+        }
+
+        Ok(())
+    }
+
+    // one N-Quad per value, or `<node> * * .` for a delete without predicates
+    fn write_nquads(&self, nquads: &mut Vec<String>, delete: bool) -> fmt::Result {
+        if self.predicates.values().any(MutationPredicateValue::is_nested) {
+            return self.flatten().iter().try_for_each(|unit| unit.write_nquads(nquads, delete));
+        }
+
+        if self.predicates.is_empty() && delete {
+            let mut nquad = String::new();
+            self.uid.write_rdf(&mut nquad)?;
+            nquad.push_str(" * * .");
+            nquads.push(nquad);
+        }
+
+        for (name, value) in self.predicates.iter().sorted_by_key(|(name, _)| *name) {
+            let objects = match value {
+                MutationPredicateValue::Edges(uids) => uids.iter().map(|uid| MutationPredicateValue::Edge(uid.clone())).collect(),
+                MutationPredicateValue::Strings(values) => values.iter().map(|value| MutationPredicateValue::String(value.clone())).collect(),
+                MutationPredicateValue::Numbers(values) => values.iter().map(|&value| MutationPredicateValue::Number(value)).collect(),
+                MutationPredicateValue::Floats(values) => values.iter().map(|&value| MutationPredicateValue::Float(value)).collect(),
+                MutationPredicateValue::List(values) => values.iter().cloned().map(MutationPredicateValue::from).collect(),
+                value => vec![value.clone()]
+            };
+
+            let (predicate, lang) = split_lang(name);
+
+            for object in objects {
+                let mut nquad = String::new();
+                self.uid.write_rdf(&mut nquad)?;
+                write!(nquad, " <{}> ", predicate)?;
+                object.write_rdf(&mut nquad)?;
+                if let Some(lang) = lang {
+                    write!(nquad, "@{}", lang)?;
+                }
+                nquad.push_str(" .");
+                nquads.push(nquad);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_schema(&self, schema: &Schema, set: bool, errors: &mut Vec<SchemaError>) {
+        if set && self.uid.uid.starts_with("_:") && !self.predicates.contains_key("dgraph.type") {
+            errors.push(SchemaError::MissingType(self.uid.uid.clone()));
+        }
+
+        for (name, value) in self.predicates.iter().sorted_by_key(|(name, _)| *name) {
+            if name.starts_with("dgraph.") {
+                continue;
+            }
+
+            match schema.predicate(name) {
+                None => errors.push(SchemaError::UnknownPredicate(name.clone())),
+                Some(def) => value.check_type(name, def.predicate_type(), errors)
+            }
+        }
+    }
+
+    fn collect_variables(&self, scope: &mut VariableScope) {
+        self.uid.collect_variables(scope);
+
+        for value in self.predicates.values() {
+            match value {
+                MutationPredicateValue::Edge(uid) => uid.collect_variables(scope),
+                MutationPredicateValue::Edges(uids) => uids.iter().for_each(|uid| uid.collect_variables(scope)),
+                _ => ()
+            }
+        }
+    }
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 #[serde(untagged)]
 pub enum MutationPredicateValue {
-    // todo: technically should support arrays
     String(String),
     Number(i64),
     Float(f64),
     Bool(bool),
     Edge(MutationUID),
     Edges(Vec<MutationUID>),
+    // values of list predicates. `List` holds any mix of scalars
+    Strings(Vec<String>),
+    Numbers(Vec<i64>),
+    Floats(Vec<f64>),
+    List(Vec<ScalarValue>),
+    // nested nodes, serialized inline as JSON objects
+    Node(MutationUnit),
+    Nodes(Vec<MutationUnit>),
+    // only meaningful in a delete, where it removes every value of the predicate
     Null
 }
 
@@ -57,9 +345,139 @@ impl MutationPredicateValue {
     pub fn string(value: &str) -> MutationPredicateValue {
         MutationPredicateValue::String(value.to_string())
     }
+
+    fn is_nested(&self) -> bool {
+        matches!(self, MutationPredicateValue::Node(_) | MutationPredicateValue::Nodes(_))
+    }
+
+    fn is_string(&self) -> bool {
+        match self {
+            MutationPredicateValue::String(_) | MutationPredicateValue::Strings(_) => true,
+            MutationPredicateValue::List(values) => values.iter().all(|value| matches!(value, ScalarValue::String(_))),
+            _ => false
+        }
+    }
+
+    // RDF has no way to write NaN or the infinities
+    fn is_finite(&self) -> bool {
+        match self {
+            MutationPredicateValue::Float(value) => value.is_finite(),
+            MutationPredicateValue::Floats(values) => values.iter().all(|value| value.is_finite()),
+            MutationPredicateValue::List(values) => values.iter().all(|value| match value {
+                ScalarValue::Float(value) => value.is_finite(),
+                _ => true
+            }),
+            _ => true
+        }
+    }
+
+    fn write_rdf(&self, w: &mut impl Write) -> fmt::Result {
+        match self {
+            MutationPredicateValue::String(value) => write_quoted(w, value),
+            MutationPredicateValue::Number(value) => write!(w, "\"{}\"^^<xs:int>", value),
+            MutationPredicateValue::Float(value) => write!(w, "\"{}\"^^<xs:float>", value),
+            MutationPredicateValue::Bool(value) => write!(w, "\"{}\"^^<xs:boolean>", value),
+            MutationPredicateValue::Edge(uid) => uid.write_rdf(w),
+            MutationPredicateValue::Edges(_)
+            | MutationPredicateValue::Strings(_)
+            | MutationPredicateValue::Numbers(_)
+            | MutationPredicateValue::Floats(_)
+            | MutationPredicateValue::List(_) => unreachable!("lists are written one N-Quad per item"),
+            MutationPredicateValue::Node(_) | MutationPredicateValue::Nodes(_) => unreachable!("nested nodes are flattened first"),
+            MutationPredicateValue::Null => w.write_char('*')
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            MutationPredicateValue::String(_) => "string",
+            MutationPredicateValue::Number(_) => "int",
+            MutationPredicateValue::Float(_) => "float",
+            MutationPredicateValue::Bool(_) => "bool",
+            MutationPredicateValue::Edge(_) => "uid",
+            MutationPredicateValue::Edges(_) => "[uid]",
+            MutationPredicateValue::Node(_) => "uid",
+            MutationPredicateValue::Nodes(_) => "[uid]",
+            MutationPredicateValue::Strings(_) => "[string]",
+            MutationPredicateValue::Numbers(_) => "[int]",
+            MutationPredicateValue::Floats(_) => "[float]",
+            MutationPredicateValue::List(_) => "list",
+            MutationPredicateValue::Null => "null"
+        }
+    }
+
+    // a [uid] predicate takes `Edges` even for a single node, so `Edge` is a mismatch there
+    fn check_type(&self, name: &str, predicate_type: &PredicateType, errors: &mut Vec<SchemaError>) {
+        let fits = match (self, predicate_type) {
+            (MutationPredicateValue::Null, _) => true,
+            // datetimes are sent as RFC 3339 strings
+            (MutationPredicateValue::String(_), PredicateType::String | PredicateType::StringArray | PredicateType::DATETIME) => true,
+            // dgraph converts ints stored into a float predicate
+            (MutationPredicateValue::Number(_), PredicateType::INT | PredicateType::INTArray | PredicateType::FLOAT | PredicateType::FLOATArray) => true,
+            (MutationPredicateValue::Float(_), PredicateType::FLOAT | PredicateType::FLOATArray) => true,
+            (MutationPredicateValue::Strings(_), PredicateType::StringArray) => true,
+            (MutationPredicateValue::Numbers(_), PredicateType::INTArray | PredicateType::FLOATArray) => true,
+            (MutationPredicateValue::Floats(_), PredicateType::FLOATArray) => true,
+            (MutationPredicateValue::List(values), predicate_type) if predicate_type.is_list() => {
+                values.iter()
+                    .map(|value| MutationPredicateValue::from(value.clone()))
+                    .for_each(|value| value.check_type(name, predicate_type, errors));
+                return;
+            },
+            (MutationPredicateValue::Bool(_), PredicateType::BOOL) => true,
+            (MutationPredicateValue::Edge(_), PredicateType::UID) => true,
+            (MutationPredicateValue::Edges(_) | MutationPredicateValue::Nodes(_), PredicateType::UIDArray) => true,
+            (MutationPredicateValue::Node(_), PredicateType::UID) => true,
+            (MutationPredicateValue::Edges(_) | MutationPredicateValue::Nodes(_), PredicateType::UID)
+            | (MutationPredicateValue::Strings(_), PredicateType::String)
+            | (MutationPredicateValue::Numbers(_), PredicateType::INT | PredicateType::FLOAT)
+            | (MutationPredicateValue::Floats(_), PredicateType::FLOAT)
+            | (MutationPredicateValue::List(_), _) => {
+                errors.push(SchemaError::ListIntoScalar(name.to_string()));
+                return;
+            },
+            _ => false
+        };
+
+        if !fits {
+            errors.push(SchemaError::TypeMismatch {
+                predicate: name.to_string(),
+                expected: predicate_type.clone(),
+                found: self.kind().to_string()
+            });
+        }
+    }
 }
 
-#[derive(Clone, Serialize)]
+// an item of `MutationPredicateValue::List`. lists of uids are `Edges`, and lists
+// can't hold lists, nested nodes or nulls
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ScalarValue {
+    String(String),
+    Number(i64),
+    Float(f64),
+    Bool(bool)
+}
+
+impl ScalarValue {
+    pub fn string(value: &str) -> ScalarValue {
+        ScalarValue::String(value.to_string())
+    }
+}
+
+impl From<ScalarValue> for MutationPredicateValue {
+    fn from(value: ScalarValue) -> Self {
+        match value {
+            ScalarValue::String(value) => MutationPredicateValue::String(value),
+            ScalarValue::Number(value) => MutationPredicateValue::Number(value),
+            ScalarValue::Float(value) => MutationPredicateValue::Float(value),
+            ScalarValue::Bool(value) => MutationPredicateValue::Bool(value)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
 pub struct MutationUID {
     uid: String
 }
@@ -71,9 +489,15 @@ impl MutationUID {
         }
     }
 
-    pub fn variable(name: &str) -> MutationUID {
+    pub fn placeholder_with(generator: &mut NameGenerator) -> MutationUID {
         Self {
-            uid: MutationUID::generate_variable(name)
+            uid: format!("_:{}", generator.next_name("node"))
+        }
+    }
+
+    pub fn variable(variable: impl Into<UidVar>) -> MutationUID {
+        Self {
+            uid: MutationUID::generate_variable(variable.into().as_str())
         }
     }
 
@@ -83,8 +507,42 @@ impl MutationUID {
         }
     }
 
+    pub fn get_uid(&self) -> String {
+        self.uid.clone()
+    }
+
+    fn check_rdf(&self) -> Result<(), RdfError> {
+        let valid = match (self.uid.strip_prefix("_:"), self.uid.strip_prefix("uid(").and_then(|uid| uid.strip_suffix(')'))) {
+            (Some(label), _) => !label.is_empty()
+                && !label.ends_with('.')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c)),
+            (_, Some(variable)) => is_valid_identifier(variable),
+            _ => is_uid_literal(&self.uid)
+        };
+
+        match valid {
+            true => Ok(()),
+            false => Err(RdfError::InvalidUid(self.uid.clone()))
+        }
+    }
+
+    // blank nodes and `uid(var)` are written as they are, uids go in angle brackets
+    fn write_rdf(&self, w: &mut impl Write) -> fmt::Result {
+        if self.uid.starts_with("_:") || self.uid.starts_with("uid(") {
+            w.write_str(&self.uid)
+        } else {
+            write!(w, "<{}>", self.uid)
+        }
+    }
+
+    fn collect_variables(&self, scope: &mut VariableScope) {
+        if let Some(variable) = self.uid.strip_prefix("uid(").and_then(|uid| uid.strip_suffix(')')) {
+            scope.use_variable(variable, VariableKind::Uid);
+        }
+    }
+
     fn generate_placeholder() -> String {
-        format!("_:uid_placeholder_{}", random::<u128>())
+        format!("_:{}", next_default_name("node"))
     }
 
     fn generate_variable(name: &str) -> String {
@@ -92,19 +550,40 @@ impl MutationUID {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum MutationType {
     SET
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum MutationLiteral {
     Literal(String),
     String(String)
 }
 
-impl ToQueryString for Mutation {
-    fn to_query_string(&self) -> String {
-        unimplemented!()
+// mutations are sent as JSON, so that's also how they're displayed; the RDF form
+// can fail and comes from `to_rdf` instead
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string_pretty(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+impl Mutation {
+    fn write_rdf(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        let mut set = vec![];
+        let mut delete = vec![];
+
+        self.set.iter().try_for_each(|unit| unit.write_nquads(&mut set, false))?;
+        self.delete.iter().try_for_each(|unit| unit.write_nquads(&mut delete, true))?;
+
+        let sections = vec![("set", set), ("delete", delete)].into_iter()
+            .filter(|(_, nquads)| !nquads.is_empty());
+
+        config.write_block(w, depth, sections, |w, (name, nquads)| {
+            write!(w, "{} ", name)?;
+            config.write_block(w, depth + 1, nquads, |w, nquad| w.write_str(&nquad))
+        })
     }
 }
@@ -0,0 +1,460 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use crate::{ToQueryString, PrettyConfig};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NameError {
+    name: String
+}
+
+impl NameError {
+    pub(crate) fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string()
+        }
+    }
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid identifier: {:?}", self.name)
+    }
+}
+
+impl std::error::Error for NameError {}
+
+pub fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        },
+        _ => false
+    }
+}
+
+pub(crate) fn validate_identifier(name: &str) -> Result<(), NameError> {
+    if is_valid_identifier(name) {
+        Ok(())
+    } else {
+        Err(NameError::new(name))
+    }
+}
+
+// literals are spliced in verbatim, so only allow the characters numbers, dates and uids
+// need, or a complete double quoted string
+pub fn is_valid_literal(literal: &str) -> bool {
+    is_quoted_literal(literal)
+        || (!literal.is_empty() && literal.chars().all(|c| c.is_ascii_alphanumeric() || "+-.:_".contains(c)))
+}
+
+fn is_quoted_literal(literal: &str) -> bool {
+    let inner = match literal.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => return false
+    };
+
+    let mut escaped = false;
+
+    for c in inner.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' | '\n' | '\r' => return false,
+            _ => ()
+        }
+    }
+
+    !escaped
+}
+
+type NameFn = dyn Fn(&str, u64) -> String + Send + Sync;
+
+// how many reserved names `next_name` skips before giving up
+const MAX_NAME_ATTEMPTS: u64 = 1000;
+
+// characters that can't appear even inside an `<iri>` predicate name
+fn is_forbidden_iri_char(c: char) -> bool {
+    c.is_whitespace() || c.is_control() || "<>\"{}|^`\\".contains(c)
+}
+
+fn is_bare_predicate_name(name: &str) -> bool {
+    let (name, lang) = match name.find('@') {
+        Some(index) => (&name[..index], Some(&name[index + 1..])),
+        None => (name, None)
+    };
+
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        && lang.map(|lang| lang.chars().all(|c| c.is_ascii_alphanumeric() || ":.-".contains(c))).unwrap_or(true)
+}
+
+// a predicate name, optionally reversed with a leading `~`. names that aren't plain
+// identifiers are rendered in `<iri>` form.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PredicateName {
+    name: String
+}
+
+impl PredicateName {
+    pub fn new(name: &str) -> Result<Self, NameError> {
+        let unreversed = name.strip_prefix('~').unwrap_or(name);
+
+        if unreversed.is_empty() || unreversed.chars().any(is_forbidden_iri_char) {
+            return Err(NameError::new(name));
+        }
+
+        Ok(PredicateName {
+            name: name.to_string()
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
+
+impl ToQueryString for PredicateName {
+    fn write_query(&self, w: &mut impl Write, _config: &PrettyConfig, _depth: usize) -> fmt::Result {
+        let (reverse, name) = match self.name.strip_prefix('~') {
+            Some(name) => ("~", name),
+            None => ("", self.name.as_str())
+        };
+
+        if is_bare_predicate_name(name) {
+            w.write_str(&self.name)
+        } else {
+            write!(w, "{}<{}>", reverse, name)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VarName(String);
+
+impl VarName {
+    pub fn new(name: &str) -> Result<Self, NameError> {
+        validate_identifier(name)?;
+        Ok(VarName(name.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ToQueryString for VarName {
+    fn write_query(&self, w: &mut impl Write, _config: &PrettyConfig, _depth: usize) -> fmt::Result {
+        w.write_str(&self.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Alias(String);
+
+impl Alias {
+    pub fn new(name: &str) -> Result<Self, NameError> {
+        validate_identifier(name)?;
+        Ok(Alias(name.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ToQueryString for Alias {
+    fn write_query(&self, w: &mut impl Write, _config: &PrettyConfig, _depth: usize) -> fmt::Result {
+        w.write_str(&self.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TypeName(String);
+
+impl TypeName {
+    pub fn new(name: &str) -> Result<Self, NameError> {
+        validate_identifier(name)?;
+        Ok(TypeName(name.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ToQueryString for TypeName {
+    fn write_query(&self, w: &mut impl Write, _config: &PrettyConfig, _depth: usize) -> fmt::Result {
+        w.write_str(&self.0)
+    }
+}
+
+// a `0x` prefixed uid literal
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Uid(String);
+
+impl Uid {
+    pub fn new(uid: &str) -> Result<Self, NameError> {
+        match is_uid_literal(uid) {
+            true => Ok(Uid(uid.to_string())),
+            false => Err(NameError::new(uid))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ToQueryString for Uid {
+    fn write_query(&self, w: &mut impl Write, _config: &PrettyConfig, _depth: usize) -> fmt::Result {
+        w.write_str(&self.0)
+    }
+}
+
+// a value spliced into the query verbatim, see `is_valid_literal`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Literal(String);
+
+impl Literal {
+    pub fn new(literal: &str) -> Result<Self, NameError> {
+        match is_valid_literal(literal) {
+            true => Ok(Literal(literal.to_string())),
+            false => Err(NameError::new(literal))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ToQueryString for Literal {
+    fn write_query(&self, w: &mut impl Write, _config: &PrettyConfig, _depth: usize) -> fmt::Result {
+        w.write_str(&self.0)
+    }
+}
+
+// conversions from strings panic on invalid names; use `new` to handle the error instead
+macro_rules! impl_from_str {
+    ($name:ident) => {
+        impl From<&str> for $name {
+            fn from(name: &str) -> Self {
+                $name::new(name).unwrap()
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(name: String) -> Self {
+                $name::new(&name).unwrap()
+            }
+        }
+    };
+}
+
+impl_from_str!(PredicateName);
+impl_from_str!(VarName);
+impl_from_str!(Alias);
+impl_from_str!(TypeName);
+impl_from_str!(Uid);
+impl_from_str!(Literal);
+
+// names are (de)serialized as plain strings and validated again on the way in
+#[cfg(feature = "serde")]
+macro_rules! impl_serde {
+    ($name:ident, $validate:expr) => {
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let name = String::deserialize(deserializer)?;
+                $validate(&name).map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "serde")]
+impl_serde!(PredicateName, PredicateName::new);
+#[cfg(feature = "serde")]
+impl_serde!(VarName, VarName::new);
+#[cfg(feature = "serde")]
+impl_serde!(Alias, Alias::new);
+#[cfg(feature = "serde")]
+impl_serde!(TypeName, TypeName::new);
+#[cfg(feature = "serde")]
+impl_serde!(Uid, Uid::new);
+#[cfg(feature = "serde")]
+impl_serde!(Literal, Literal::new);
+
+// escapes unescaped `/`, and a trailing lone `\`, so the pattern can't terminate the
+// `/regex/` literal early
+pub fn escape_regex(pattern: &str) -> String {
+    let mut escaped = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                escaped.push(c);
+                match chars.next() {
+                    Some('\n') => escaped.push('n'),
+                    Some(next) => escaped.push(next),
+                    None => escaped.push('\\')
+                }
+            },
+            '/' => escaped.push_str("\\/"),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c)
+        }
+    }
+
+    escaped
+}
+
+pub(crate) fn is_uid_literal(uid: &str) -> bool {
+    uid.strip_prefix("0x")
+        .map(|hex| !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .unwrap_or(false)
+}
+
+#[derive(Clone)]
+enum Strategy {
+    Sequential,
+    Seeded(Box<StdRng>),
+    Custom(Arc<NameFn>)
+}
+
+#[derive(Clone)]
+pub struct NameGenerator {
+    next: u64,
+    strategy: Strategy,
+    taken: HashSet<String>
+}
+
+impl NameGenerator {
+    pub fn new() -> Self {
+        Self::sequential()
+    }
+
+    // `prefix_0`, `prefix_1`, ...
+    pub fn sequential() -> Self {
+        Self::starting_at(0)
+    }
+
+    pub fn starting_at(start: u64) -> Self {
+        Self::with_strategy(start, Strategy::Sequential)
+    }
+
+    // `prefix_<hex>`, where the suffixes come from an rng seeded with `seed`
+    pub fn seeded(seed: u64) -> Self {
+        Self::with_strategy(0, Strategy::Seeded(Box::new(StdRng::seed_from_u64(seed))))
+    }
+
+    // `f` receives the prefix and the number of names generated so far. when this is
+    // the default generator, `f` runs while it is borrowed and must not draw names from
+    // it again (`Variable::random`, `MutationUID::placeholder`, ...)
+    pub fn custom<F>(f: F) -> Self
+        where F: Fn(&str, u64) -> String + Send + Sync + 'static
+    {
+        Self::with_strategy(0, Strategy::Custom(Arc::new(f)))
+    }
+
+    fn with_strategy(start: u64, strategy: Strategy) -> Self {
+        Self {
+            next: start,
+            strategy,
+            taken: HashSet::new()
+        }
+    }
+
+    // names handed out by `next_name` will never collide with a reserved one
+    pub fn reserve(&mut self, name: &str) {
+        self.taken.insert(name.to_string());
+    }
+
+    // names a custom strategy produces that aren't identifiers are skipped like reserved
+    // ones; panics if the strategy keeps producing names that can't be used
+    pub fn next_name(&mut self, prefix: &str) -> String {
+        for _ in 0..MAX_NAME_ATTEMPTS {
+            let name = match &mut self.strategy {
+                Strategy::Sequential => format!("{}_{}", prefix, self.next),
+                Strategy::Seeded(rng) => format!("{}_{:x}", prefix, rng.gen::<u64>()),
+                Strategy::Custom(f) => f(prefix, self.next)
+            };
+            self.next += 1;
+
+            if is_valid_identifier(&name) && !self.taken.contains(&name) {
+                return name;
+            }
+        }
+
+        panic!("No valid unreserved name for {:?} after {} attempts", prefix, MAX_NAME_ATTEMPTS)
+    }
+}
+
+impl fmt::Debug for NameGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let strategy = match self.strategy {
+            Strategy::Sequential => "Sequential",
+            Strategy::Seeded(_) => "Seeded",
+            Strategy::Custom(_) => "Custom"
+        };
+
+        f.debug_struct("NameGenerator")
+            .field("next", &self.next)
+            .field("strategy", &strategy)
+            .finish()
+    }
+}
+
+impl Default for NameGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// by default `Variable::random` and `MutationUID::placeholder` count up across the whole
+// process, so fragments built on different threads can be combined without collisions
+static NEXT_DEFAULT_NAME: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static DEFAULT_GENERATOR: RefCell<Option<NameGenerator>> = const { RefCell::new(None) };
+}
+
+// replaces the generator backing `Variable::random` and `MutationUID::placeholder` on
+// this thread only, e.g. to get reproducible names in a test
+pub fn set_default_generator(generator: NameGenerator) {
+    DEFAULT_GENERATOR.with(|default| *default.borrow_mut() = Some(generator));
+}
+
+pub fn reset_default_generator(start: u64) {
+    set_default_generator(NameGenerator::starting_at(start));
+}
+
+// goes back to the process wide counter on this thread
+pub fn clear_default_generator() {
+    DEFAULT_GENERATOR.with(|default| *default.borrow_mut() = None);
+}
+
+pub(crate) fn next_default_name(prefix: &str) -> String {
+    DEFAULT_GENERATOR.with(|generator| {
+        let mut generator = generator.try_borrow_mut()
+            .expect("The default name generator was used from inside its own custom strategy");
+
+        match generator.as_mut() {
+            Some(generator) => generator.next_name(prefix),
+            None => format!("{}_{}", prefix, NEXT_DEFAULT_NAME.fetch_add(1, Ordering::Relaxed))
+        }
+    })
+}
@@ -0,0 +1,958 @@
+use crate::condition::{Condition, ConditionSubject, ConditionValue};
+use crate::directive::Directive;
+use crate::names::{PredicateName, VarName, Alias, Literal, TypeName, Uid};
+use crate::predicate::{Predicate, Field, Variable, Expand, ExpandTarget};
+use crate::query::{Query, QueryParam, ParamType};
+use crate::queryblock::{QueryBlock, QueryBlockType, QueryOrder, RootFunction, UidRef};
+use crate::{QueryBuilder, QueryBlockBuilder, EdgeBuilder};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.message, self.line, self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Iri(String),
+    Str(String),
+    Regexp(String),
+    Param(String),
+    Directive(String),
+    Punct(char),
+    Eof
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "`{}`", word),
+            Token::Iri(iri) => write!(f, "`<{}>`", iri),
+            Token::Str(_) => write!(f, "string"),
+            Token::Regexp(_) => write!(f, "regular expression"),
+            Token::Param(name) => write!(f, "`${}`", name),
+            Token::Directive(name) => write!(f, "`@{}`", name),
+            Token::Punct(c) => write!(f, "`{}`", c),
+            Token::Eof => write!(f, "end of input")
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Position {
+    line: usize,
+    column: usize
+}
+
+const DIRECTIVES: &[&str] = &["filter", "cascade", "normalize", "ignorereflex"];
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_.~+-@".contains(c)
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    index: usize,
+    position: Position
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.index += 1;
+
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+
+        Some(c)
+    }
+
+    fn error<T>(&self, message: &str, position: Position) -> Result<T, ParseError> {
+        Err(ParseError {
+            message: message.to_string(),
+            line: position.line,
+            column: position.column
+        })
+    }
+
+    // `name@en` is a language tag but `name@filter(...)` is a directive
+    fn starts_directive(&self) -> bool {
+        DIRECTIVES.iter().any(|directive| {
+            let end = self.index + 1 + directive.len();
+
+            end <= self.chars.len()
+                && self.chars[self.index + 1..end].iter().copied().eq(directive.chars())
+                && !self.chars.get(end).map(|&c| c.is_ascii_alphanumeric() || c == '_').unwrap_or(false)
+        })
+    }
+
+    fn string(&mut self, start: Position) -> Result<Token, ParseError> {
+        let mut literal = String::from("\"");
+
+        loop {
+            match self.next() {
+                Some('\\') => {
+                    literal.push('\\');
+                    if let Some(escaped) = self.next() {
+                        literal.push(escaped);
+                    }
+                },
+                Some('"') => break,
+                Some(c) => literal.push(c),
+                None => return self.error("unterminated string", start)
+            }
+        }
+        literal.push('"');
+
+        match serde_json::from_str(&literal) {
+            Ok(value) => Ok(Token::Str(value)),
+            Err(_) => self.error("invalid string escape", start)
+        }
+    }
+
+    fn regexp(&mut self, start: Position) -> Result<Token, ParseError> {
+        let mut pattern = String::new();
+
+        loop {
+            match self.next() {
+                Some('\\') => match self.next() {
+                    Some('/') => pattern.push('/'),
+                    Some(escaped) => {
+                        pattern.push('\\');
+                        pattern.push(escaped);
+                    },
+                    None => pattern.push('\\')
+                },
+                Some('/') => break,
+                Some(c) => pattern.push(c),
+                None => return self.error("unterminated regular expression", start)
+            }
+        }
+
+        if self.peek().map(|c| c.is_ascii_alphabetic()).unwrap_or(false) {
+            return self.error("regular expression flags are not supported", self.position);
+        }
+
+        Ok(Token::Regexp(pattern))
+    }
+
+    fn iri(&mut self, start: Position) -> Result<Token, ParseError> {
+        let mut iri = String::new();
+
+        loop {
+            match self.next() {
+                Some('>') => return Ok(Token::Iri(iri)),
+                Some(c) => iri.push(c),
+                None => return self.error("unterminated predicate name", start)
+            }
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_') {
+                break;
+            }
+            self.next();
+            name.push(c);
+        }
+
+        name
+    }
+
+    fn word(&mut self, first: char) -> Token {
+        let mut word = first.to_string();
+
+        while let Some(c) = self.peek() {
+            if !is_word_char(c) || (c == '@' && self.starts_directive()) {
+                break;
+            }
+            self.next();
+            word.push(c);
+        }
+
+        Token::Word(word)
+    }
+
+    fn tokenize(mut self) -> Result<Vec<(Token, Position)>, ParseError> {
+        let mut tokens = vec![];
+
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.next();
+                continue;
+            }
+
+            if c == '#' {
+                while self.peek().map(|c| c != '\n').unwrap_or(false) {
+                    self.next();
+                }
+                continue;
+            }
+
+            let start = self.position;
+            self.next();
+
+            let token = match c {
+                '{' | '}' | '(' | ')' | '[' | ']' | ',' | ':' | '=' => Token::Punct(c),
+                '"' => self.string(start)?,
+                '/' => self.regexp(start)?,
+                '<' => self.iri(start)?,
+                '~' if self.peek() == Some('<') => Token::Punct('~'),
+                '$' | '@' => {
+                    let name = self.name();
+
+                    if name.is_empty() {
+                        return self.error(&format!("expected a name after `{}`", c), start);
+                    }
+
+                    if c == '$' { Token::Param(name) } else { Token::Directive(name) }
+                },
+                c if is_word_char(c) => self.word(c),
+                c => return self.error(&format!("unexpected character `{}`", c), start)
+            };
+
+            tokens.push((token, start));
+        }
+
+        tokens.push((Token::Eof, self.position));
+        Ok(tokens)
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    index: usize
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].0
+    }
+
+    fn peek_nth(&self, n: usize) -> &Token {
+        let index = (self.index + n).min(self.tokens.len() - 1);
+        &self.tokens[index].0
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.index].0.clone();
+        if self.index < self.tokens.len() - 1 {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, ParseError> {
+        let position = self.tokens[self.index].1;
+
+        Err(ParseError {
+            message,
+            line: position.line,
+            column: position.column
+        })
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, ParseError> {
+        self.error(format!("expected {}, found {}", expected, self.peek()))
+    }
+
+    fn is_punct(&self, c: char) -> bool {
+        *self.peek() == Token::Punct(c)
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Token::Word(w) if w.eq_ignore_ascii_case(word))
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), ParseError> {
+        if self.is_punct(c) {
+            self.next();
+            Ok(())
+        } else {
+            self.unexpected(&format!("`{}`", c))
+        }
+    }
+
+    fn expect_word(&mut self) -> Result<String, ParseError> {
+        match self.peek().clone() {
+            Token::Word(word) => {
+                self.next();
+                Ok(word)
+            },
+            _ => self.unexpected("a name")
+        }
+    }
+
+    fn var_name(&mut self) -> Result<VarName, ParseError> {
+        let name = self.expect_word()?;
+
+        match VarName::new(&name) {
+            Ok(var) => Ok(var),
+            Err(err) => {
+                self.index -= 1;
+                self.error(err.to_string())
+            }
+        }
+    }
+
+    fn type_name(&mut self) -> Result<TypeName, ParseError> {
+        let name = self.expect_word()?;
+
+        match TypeName::new(&name) {
+            Ok(name) => Ok(name),
+            Err(err) => {
+                self.index -= 1;
+                self.error(err.to_string())
+            }
+        }
+    }
+
+    // a uid literal or the name of a uid variable
+    fn uid_ref(&mut self) -> Result<UidRef, ParseError> {
+        let word = self.expect_word()?;
+
+        let uid = match word.starts_with("0x") {
+            true => Uid::new(&word).map(UidRef::Uid),
+            false => VarName::new(&word).map(|name| UidRef::Var(name.into()))
+        };
+
+        uid.or_else(|_| {
+            self.index -= 1;
+            self.error(format!("invalid uid `{}`", word))
+        })
+    }
+
+    fn uid_refs(&mut self) -> Result<Vec<UidRef>, ParseError> {
+        let mut refs = vec![self.uid_ref()?];
+        while self.is_punct(',') {
+            self.next();
+            refs.push(self.uid_ref()?);
+        }
+        Ok(refs)
+    }
+
+    fn alias(&mut self) -> Result<Option<Alias>, ParseError> {
+        match (self.peek().clone(), self.peek_nth(1)) {
+            (Token::Word(name), Token::Punct(':')) => match Alias::new(&name) {
+                Ok(alias) => {
+                    self.next();
+                    self.next();
+                    Ok(Some(alias))
+                },
+                Err(err) => self.error(err.to_string())
+            },
+            _ => Ok(None)
+        }
+    }
+
+    fn predicate_name(&mut self) -> Result<String, ParseError> {
+        let name = match self.peek().clone() {
+            Token::Word(word) => word,
+            Token::Iri(iri) => iri,
+            Token::Punct('~') => match self.peek_nth(1).clone() {
+                Token::Iri(iri) => {
+                    self.next();
+                    format!("~{}", iri)
+                },
+                _ => return self.unexpected("a predicate name")
+            },
+            _ => return self.unexpected("a predicate name")
+        };
+
+        if let Err(err) = PredicateName::new(&name) {
+            return self.error(err.to_string());
+        }
+
+        self.next();
+        Ok(name)
+    }
+
+    fn query(&mut self) -> Result<Query, ParseError> {
+        let mut builder = QueryBuilder::default();
+
+        if self.is_word("query") {
+            self.next();
+
+            if let Token::Word(name) = self.peek().clone() {
+                if let Err(err) = VarName::new(&name) {
+                    return self.error(err.to_string());
+                }
+                self.next();
+                builder.name(name);
+            }
+
+            if self.is_punct('(') {
+                self.next();
+                builder.params(self.params()?);
+            }
+        }
+
+        self.expect_punct('{')?;
+
+        let mut query_blocks = vec![];
+        while !self.is_punct('}') {
+            query_blocks.push(self.query_block()?);
+        }
+        self.expect_punct('}')?;
+
+        if *self.peek() != Token::Eof {
+            return self.unexpected("end of input");
+        }
+
+        builder.query_blocks(query_blocks);
+        builder.build().or_else(|err| self.error(err))
+    }
+
+    fn params(&mut self) -> Result<Vec<QueryParam>, ParseError> {
+        let mut params = vec![];
+
+        while !self.is_punct(')') {
+            let name = match self.peek().clone() {
+                Token::Param(name) => name,
+                _ => return self.unexpected("a `$variable`")
+            };
+            if let Err(err) = VarName::new(&name) {
+                return self.error(err.to_string());
+            }
+            self.next();
+            self.expect_punct(':')?;
+
+            let param_type = match self.expect_word()?.as_str() {
+                "string" => ParamType::String,
+                "int" => ParamType::Int,
+                "float" => ParamType::Float,
+                "bool" => ParamType::Bool,
+                other => {
+                    self.index -= 1;
+                    return self.error(format!("unknown variable type `{}`", other));
+                }
+            };
+
+            let mut param = QueryParam::new(&name, param_type);
+
+            if self.is_punct('=') {
+                self.next();
+                param = match self.next() {
+                    Token::Str(value) | Token::Word(value) => match param.try_default(&value) {
+                        Ok(param) => param,
+                        Err(err) => {
+                            self.index -= 1;
+                            return self.error(err.to_string());
+                        }
+                    },
+                    _ => {
+                        self.index -= 1;
+                        return self.unexpected("a default value");
+                    }
+                };
+            }
+
+            params.push(param);
+
+            if !self.is_punct(')') {
+                self.expect_punct(',')?;
+            }
+        }
+
+        self.expect_punct(')')?;
+        Ok(params)
+    }
+
+    fn query_block(&mut self) -> Result<QueryBlock, ParseError> {
+        let mut builder = QueryBlockBuilder::default();
+
+        if let Token::Word(word) = self.peek_nth(1) {
+            if word == "as" {
+                builder.variable(self.var_name()?);
+                self.next();
+            }
+        }
+
+        let name = self.expect_word()?;
+        if name == "var" {
+            builder.query_type(QueryBlockType::Var);
+        } else {
+            match QueryBlockType::try_named(&name) {
+                Ok(query_type) => builder.query_type(query_type),
+                Err(err) => {
+                    self.index -= 1;
+                    return self.error(err.to_string());
+                }
+            };
+        }
+
+        self.expect_punct('(')?;
+        if self.expect_word()? != "func" {
+            self.index -= 1;
+            return self.unexpected("`func`");
+        }
+        self.expect_punct(':')?;
+        if self.is_word("type") && *self.peek_nth(1) == Token::Punct('(') {
+            self.next();
+            self.next();
+            let name = self.type_name()?;
+            self.expect_punct(')')?;
+            builder.root_filter(RootFunction::Type(name));
+        } else if self.is_word("uid") && *self.peek_nth(1) == Token::Punct('(') {
+            // the builder's `RootFunction::uid`, rather than a `Condition::UID`
+            self.next();
+            self.next();
+            builder.root_filter(RootFunction::Uid(self.uid_refs()?));
+            self.expect_punct(')')?;
+        } else {
+            builder.root_filter(self.function()?);
+        }
+
+        while self.is_punct(',') {
+            self.next();
+            let argument = self.expect_word()?;
+            self.expect_punct(':')?;
+
+            match argument.as_str() {
+                "orderasc" => builder.order(QueryOrder::ASC(self.order_predicate()?)),
+                "orderdesc" => builder.order(QueryOrder::DESC(self.order_predicate()?)),
+                "first" => {
+                    let first = self.expect_word()?;
+                    match first.parse() {
+                        Ok(first) => builder.first(first),
+                        Err(_) => {
+                            self.index -= 1;
+                            return self.unexpected("an integer");
+                        }
+                    }
+                },
+                other => {
+                    self.index -= 2;
+                    return self.error(format!("unsupported argument `{}`", other));
+                }
+            };
+        }
+        self.expect_punct(')')?;
+
+        while let Token::Directive(directive) = self.peek().clone() {
+            match directive.as_str() {
+                "filter" => {
+                    self.next();
+                    builder.filter(self.filter()?);
+                },
+                _ => {
+                    builder.directive(self.directive()?);
+                }
+            };
+        }
+
+        builder.predicates(self.selection_set()?);
+        builder.build().or_else(|err| self.error(err))
+    }
+
+    fn order_predicate(&mut self) -> Result<Predicate, ParseError> {
+        if self.is_word("val") && *self.peek_nth(1) == Token::Punct('(') {
+            self.next();
+            self.next();
+            let variable = self.var_name()?;
+            self.expect_punct(')')?;
+            Ok(Predicate::Val(Variable::new(variable.as_str())))
+        } else {
+            Ok(Predicate::Field(Field::new(&self.predicate_name()?)))
+        }
+    }
+
+    fn filter(&mut self) -> Result<Condition, ParseError> {
+        self.expect_punct('(')?;
+        let condition = self.condition()?;
+        self.expect_punct(')')?;
+        Ok(condition)
+    }
+
+    fn selection_set(&mut self) -> Result<Vec<Predicate>, ParseError> {
+        self.expect_punct('{')?;
+
+        let mut predicates = vec![];
+        while !self.is_punct('}') {
+            predicates.push(self.predicate()?);
+        }
+        self.expect_punct('}')?;
+
+        Ok(predicates)
+    }
+
+    fn predicate(&mut self) -> Result<Predicate, ParseError> {
+        if let Token::Word(word) = self.peek_nth(1) {
+            if word == "as" {
+                let variable = self.var_name()?;
+                self.next();
+
+                let alias = self.alias()?;
+                let name = self.predicate_name()?;
+
+                return if self.is_punct('{') || matches!(self.peek(), Token::Directive(_)) {
+                    Ok(Predicate::EdgeVariable(variable.into(), self.edge(name, alias)?))
+                } else if alias.is_some() {
+                    self.error("aliases are not supported on scalar variables".to_string())
+                } else if name == "uid" {
+                    Ok(Predicate::UidVariable(variable.into()))
+                } else {
+                    Ok(Predicate::ScalarVariable(variable.into(), Field::new(&name)))
+                };
+            }
+        }
+
+        let alias = self.alias()?;
+
+        if self.is_word("expand") && *self.peek_nth(1) == Token::Punct('(') {
+            if alias.is_some() {
+                return self.error("aliases are not supported on expand".to_string());
+            }
+
+            return self.expand();
+        }
+
+        if *self.peek_nth(1) == Token::Punct('(') {
+            if self.is_word("count") || self.is_word("val") {
+                let function = self.expect_word()?;
+                self.next();
+
+                let predicate = if function == "count" {
+                    let mut field = Field::new(&self.predicate_name()?);
+                    if let Some(alias) = &alias {
+                        field = field.alias(alias.as_str());
+                    }
+                    Predicate::Count(field)
+                } else {
+                    let mut variable = Variable::new(self.var_name()?.as_str());
+                    if let Some(alias) = &alias {
+                        variable = variable.alias(alias.as_str());
+                    }
+                    Predicate::Val(variable)
+                };
+
+                self.expect_punct(')')?;
+                return Ok(predicate);
+            }
+
+            return self.unexpected("a predicate");
+        }
+
+        let name = self.predicate_name()?;
+
+        if self.is_punct('{') || matches!(self.peek(), Token::Directive(_)) {
+            Ok(Predicate::Edge(self.edge(name, alias)?))
+        } else {
+            let mut field = Field::new(&name);
+            if let Some(alias) = &alias {
+                field = field.alias(alias.as_str());
+            }
+            Ok(Predicate::Field(field))
+        }
+    }
+
+    fn directive(&mut self) -> Result<Directive, ParseError> {
+        let name = match self.next() {
+            Token::Directive(name) => name,
+            _ => {
+                self.index -= 1;
+                return self.unexpected("a directive");
+            }
+        };
+
+        match name.as_str() {
+            "cascade" if self.is_punct('(') => {
+                self.next();
+
+                let mut predicates = vec![self.predicate_name()?.into()];
+                while self.is_punct(',') {
+                    self.next();
+                    predicates.push(self.predicate_name()?.into());
+                }
+
+                self.expect_punct(')')?;
+                Ok(Directive::Cascade(predicates))
+            },
+            "cascade" => Ok(Directive::cascade()),
+            "normalize" => Ok(Directive::Normalize),
+            "ignorereflex" => Ok(Directive::IgnoreReflex),
+            other => {
+                self.index -= 1;
+                self.error(format!("unsupported directive `@{}`", other))
+            }
+        }
+    }
+
+    fn expand(&mut self) -> Result<Predicate, ParseError> {
+        self.next();
+        self.expect_punct('(')?;
+
+        let target = if self.is_word("_all_") {
+            self.next();
+            ExpandTarget::All
+        } else if self.is_word("val") && *self.peek_nth(1) == Token::Punct('(') {
+            self.next();
+            self.next();
+            let variable = self.var_name()?;
+            self.expect_punct(')')?;
+            ExpandTarget::Val(variable.into())
+        } else {
+            let mut types = vec![self.type_name()?];
+            while self.is_punct(',') {
+                self.next();
+                types.push(self.type_name()?);
+            }
+            ExpandTarget::Types(types)
+        };
+
+        self.expect_punct(')')?;
+        let mut expand = Expand::new(target);
+
+        while let Token::Directive(directive) = self.peek().clone() {
+            match directive.as_str() {
+                "filter" => {
+                    self.next();
+                    let filter = self.filter()?;
+                    expand = expand.filter(filter).or_else(|err| self.error(err))?;
+                },
+                other => return self.error(format!("unsupported directive `@{}`", other))
+            };
+        }
+
+        if self.is_punct('{') {
+            expand = expand.predicates(self.selection_set()?);
+        }
+
+        Ok(Predicate::Expand(expand))
+    }
+
+    fn edge(&mut self, name: String, alias: Option<Alias>) -> Result<crate::predicate::Edge, ParseError> {
+        let mut builder = EdgeBuilder::default();
+        builder.name(name);
+
+        if let Some(alias) = alias {
+            builder.alias(alias);
+        }
+
+        while let Token::Directive(directive) = self.peek().clone() {
+            match directive.as_str() {
+                "filter" => {
+                    self.next();
+                    builder.filter(self.filter()?);
+                },
+                "ignorereflex" => return self.error("`@ignorereflex` is only allowed on a query block".to_string()),
+                _ => {
+                    builder.directive(self.directive()?);
+                }
+            };
+        }
+
+        builder.predicates(self.selection_set()?);
+        builder.build().or_else(|err| self.error(err))
+    }
+
+    // or-expressions bind loosest, then and, then not
+    fn condition(&mut self) -> Result<Condition, ParseError> {
+        let mut condition = self.and_condition()?;
+
+        while self.is_word("or") {
+            self.next();
+            condition = Condition::OR(Box::new(condition), Box::new(self.and_condition()?));
+        }
+
+        Ok(condition)
+    }
+
+    fn and_condition(&mut self) -> Result<Condition, ParseError> {
+        let mut condition = self.unary_condition()?;
+
+        while self.is_word("and") {
+            self.next();
+            condition = Condition::AND(Box::new(condition), Box::new(self.unary_condition()?));
+        }
+
+        Ok(condition)
+    }
+
+    fn unary_condition(&mut self) -> Result<Condition, ParseError> {
+        if self.is_word("not") {
+            self.next();
+            return Ok(Condition::NOT(Box::new(self.unary_condition()?)));
+        }
+
+        if self.is_punct('(') {
+            self.next();
+            let condition = self.condition()?;
+            self.expect_punct(')')?;
+            return Ok(condition);
+        }
+
+        self.function()
+    }
+
+    fn function(&mut self) -> Result<Condition, ParseError> {
+        let function = self.expect_word()?;
+        self.expect_punct('(')?;
+
+        let condition = match function.as_str() {
+            "uid" => {
+                Condition::UID(self.uid_refs()?)
+            },
+            "has" => Condition::HAS(self.predicate_name()?.into()),
+            "regexp" => {
+                let predicate = self.predicate_name()?.into();
+                self.expect_punct(',')?;
+
+                match self.next() {
+                    Token::Regexp(pattern) => Condition::REGEXP(predicate, ConditionValue::Regexp(pattern)),
+                    _ => {
+                        self.index -= 1;
+                        return self.unexpected("a regular expression");
+                    }
+                }
+            },
+            "eq" | "ge" | "gt" | "le" | "lt" => {
+                let subject = self.condition_subject()?;
+                self.expect_punct(',')?;
+                let value = self.value()?;
+
+                match function.as_str() {
+                    "eq" => Condition::EQ(subject, value),
+                    "ge" => Condition::GE(subject, value),
+                    "gt" => Condition::GT(subject, value),
+                    "le" => Condition::LE(subject, value),
+                    _ => Condition::LT(subject, value)
+                }
+            },
+            other => {
+                self.index -= 2;
+                return self.error(format!("unsupported function `{}`", other));
+            }
+        };
+
+        self.expect_punct(')')?;
+        Ok(condition)
+    }
+
+    fn condition_subject(&mut self) -> Result<ConditionSubject, ParseError> {
+        let function = match self.peek() {
+            Token::Word(word) if *self.peek_nth(1) == Token::Punct('(') => word.clone(),
+            _ => return Ok(ConditionSubject::Predicate(self.predicate_name()?.into()))
+        };
+
+        self.next();
+        self.next();
+
+        let subject = match function.as_str() {
+            "count" => ConditionSubject::Count(self.predicate_name()?.into()),
+            "len" => ConditionSubject::Len(self.var_name()?.into()),
+            "val" => ConditionSubject::Val(self.var_name()?.into()),
+            other => {
+                self.index -= 2;
+                return self.error(format!("unsupported function `{}`", other));
+            }
+        };
+
+        self.expect_punct(')')?;
+        Ok(subject)
+    }
+
+    fn value(&mut self) -> Result<ConditionValue, ParseError> {
+        match self.peek().clone() {
+            Token::Str(value) => {
+                self.next();
+                Ok(ConditionValue::String(value))
+            },
+            Token::Regexp(pattern) => {
+                self.next();
+                Ok(ConditionValue::Regexp(pattern))
+            },
+            Token::Param(name) => match VarName::new(&name) {
+                Ok(name) => {
+                    self.next();
+                    Ok(ConditionValue::Param(name))
+                },
+                Err(err) => self.error(err.to_string())
+            },
+            Token::Word(word) if word == "val" && *self.peek_nth(1) == Token::Punct('(') => {
+                self.next();
+                self.next();
+                let variable = self.var_name()?;
+                self.expect_punct(')')?;
+                Ok(ConditionValue::Val(variable.into()))
+            },
+            Token::Word(word) => match Literal::new(&word) {
+                Ok(literal) => {
+                    self.next();
+                    Ok(ConditionValue::Literal(literal))
+                },
+                Err(_) => self.error(format!("invalid literal `{}`", word))
+            },
+            Token::Punct('[') => {
+                self.next();
+
+                let value = match self.peek() {
+                    Token::Str(_) => {
+                        let mut items = vec![];
+                        while let Token::Str(item) = self.peek().clone() {
+                            self.next();
+                            items.push(item);
+                            if !self.is_punct(']') {
+                                self.expect_punct(',')?;
+                            }
+                        }
+                        ConditionValue::StringArr(items)
+                    },
+                    _ => {
+                        let mut items = vec![];
+                        while let Token::Word(item) = self.peek().clone() {
+                            match Literal::new(&item) {
+                                Ok(literal) => items.push(literal),
+                                Err(_) => return self.error(format!("invalid literal `{}`", item))
+                            }
+                            self.next();
+                            if !self.is_punct(']') {
+                                self.expect_punct(',')?;
+                            }
+                        }
+                        ConditionValue::NumArr(items)
+                    }
+                };
+
+                self.expect_punct(']')?;
+                Ok(value)
+            },
+            _ => self.unexpected("a value")
+        }
+    }
+}
+
+pub fn parse_query(input: &str) -> Result<Query, ParseError> {
+    let mut parser = Parser {
+        tokens: Lexer {
+            chars: input.chars().collect(),
+            index: 0,
+            position: Position { line: 1, column: 1 }
+        }.tokenize()?,
+        index: 0
+    };
+
+    parser.query()
+}
@@ -1,134 +1,420 @@
 use derive_builder::Builder;
-use crate::{ToQueryString, IndentedString};
-use itertools::Itertools;
-use crate::condition::Condition;
+use crate::{ToQueryString, PrettyConfig};
+use std::fmt::{self, Write};
+use crate::condition::{Condition, validate_filter};
+use crate::directive::{Directive, write_directives};
+use crate::names::{next_default_name, NameGenerator, PredicateName, TypeName, VarName, Alias};
+use crate::printer::write_separated;
+use crate::validation::{SchemaCheck, VariableKind, VariableScope};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Predicate {
     Count(Field),
     Field(Field),
     Edge(Edge),
     Val(Variable),
-    ScalarVariable(String, Field),
-    EdgeVariable(String, Edge)
+    ScalarVariable(ValueVar, Field),
+    UidVariable(UidVar),
+    EdgeVariable(UidVar, Edge),
+    Expand(Expand)
 }
 
-#[derive(Clone, Debug)]
+impl Predicate {
+    // `count(uid)`, the number of nodes a block or edge matched
+    pub fn count_uid() -> Self {
+        Predicate::Count(Field::new("uid"))
+    }
+
+    // the definitions below hand back the variable so it can only be used
+    // where its kind of variable is accepted
+
+    pub fn value_variable(name: &str, field: Field) -> (ValueVar, Predicate) {
+        let variable = ValueVar::new(name);
+        (variable.clone(), Predicate::ScalarVariable(variable, field))
+    }
+
+    pub fn uid_variable(name: &str) -> (UidVar, Predicate) {
+        let variable = UidVar::new(name);
+        (variable.clone(), Predicate::UidVariable(variable))
+    }
+
+    pub fn edge_variable(name: &str, edge: Edge) -> (UidVar, Predicate) {
+        let variable = UidVar::new(name);
+        (variable.clone(), Predicate::EdgeVariable(variable, edge))
+    }
+
+    pub(crate) fn check_schema(&self, check: &mut SchemaCheck) {
+        match self {
+            Predicate::Count(field) | Predicate::ScalarVariable(_, field) => check.predicate(&field.name),
+            Predicate::Field(field) => check.field(&field.name),
+            Predicate::Edge(edge) | Predicate::EdgeVariable(_, edge) => edge.check_schema(check),
+            Predicate::Val(_) | Predicate::UidVariable(_) => (),
+            Predicate::Expand(expand) => {
+                expand.filter.iter().for_each(|filter| filter.check_schema(check, false));
+                expand.predicates.iter().for_each(|predicate| predicate.check_schema(check));
+            }
+        }
+    }
+
+    pub(crate) fn collect_variables(&self, scope: &mut VariableScope) {
+        match self {
+            Predicate::Count(_) | Predicate::Field(_) => (),
+            Predicate::Edge(edge) => edge.collect_variables(scope),
+            Predicate::Val(variable) => scope.use_variable(variable.name.as_str(), VariableKind::Value),
+            Predicate::ScalarVariable(variable, _) => scope.define(variable.as_str(), VariableKind::Value),
+            Predicate::UidVariable(variable) => scope.define(variable.as_str(), VariableKind::Uid),
+            Predicate::EdgeVariable(variable, edge) => {
+                scope.define(variable.as_str(), VariableKind::Uid);
+                edge.collect_variables(scope);
+            },
+            Predicate::Expand(expand) => expand.collect_variables(scope)
+        }
+    }
+}
+
+// handles for the two kinds of query variables: uid variables hold a set of
+// nodes (`uid(X)`, `len(X)`) and value variables hold a value per node (`val(X)`)
+macro_rules! impl_variable_handle {
+    ($name:ident) => {
+        #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+        pub struct $name(VarName);
+
+        impl $name {
+            pub fn new(name: &str) -> Self {
+                $name(name.into())
+            }
+
+            pub fn random() -> Self {
+                Self::new(&next_default_name("var"))
+            }
+
+            pub fn random_with(generator: &mut NameGenerator) -> Self {
+                Self::new(&generator.next_name("var"))
+            }
+
+            pub fn name(&self) -> &VarName {
+                &self.0
+            }
+
+            pub fn as_str(&self) -> &str {
+                self.0.as_str()
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(name: &str) -> Self {
+                $name::new(name)
+            }
+        }
+
+        impl From<&$name> for $name {
+            fn from(variable: &$name) -> Self {
+                variable.clone()
+            }
+        }
+
+        impl From<VarName> for $name {
+            fn from(name: VarName) -> Self {
+                $name(name)
+            }
+        }
+
+        impl ToQueryString for $name {
+            fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+                self.0.write_query(w, config, depth)
+            }
+        }
+    };
+}
+
+impl_variable_handle!(UidVar);
+impl_variable_handle!(ValueVar);
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Variable {
-    name: String,
-    alias: Option<String>
+    name: ValueVar,
+    alias: Option<Alias>
+}
+
+impl From<ValueVar> for Variable {
+    fn from(name: ValueVar) -> Self {
+        Self {
+            name,
+            alias: None
+        }
+    }
 }
 
 impl Variable {
     pub fn new(name: &str) -> Self {
         Self {
-            name: name.to_string(),
+            name: name.into(),
             alias: None
         }
     }
 
     pub fn alias(mut self, alias: &str) -> Self {
-        self.alias = Some(alias.to_string());
+        self.alias = Some(alias.into());
         self
     }
 
     pub fn random() -> Self {
-        Self::new(&format!("var_{}", rand::random::<u128>()))
+        Self::new(&next_default_name("var"))
+    }
+
+    pub fn random_with(generator: &mut NameGenerator) -> Self {
+        Self::new(&generator.next_name("var"))
     }
 
     pub fn get_name(&self) -> String {
-        self.name.clone()
+        self.name.as_str().to_string()
     }
 
-    fn get_alias(&self) -> String {
-        self.alias.clone()
-            .map(|alias| format!("{} : ", alias))
-            .unwrap_or("".to_string())
+    pub fn var(&self) -> &ValueVar {
+        &self.name
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Field {
-    name: String,
-    alias: Option<String>
+    name: PredicateName,
+    alias: Option<Alias>
 }
 
 impl Field {
     pub fn new(name: &str) -> Self {
         Self {
-            name: name.to_string(),
+            name: name.into(),
             alias: None
         }
     }
 
     pub fn alias(mut self, alias: &str) -> Self {
-        self.alias = Some(alias.to_string());
+        self.alias = Some(alias.into());
         self
     }
-
-    fn get_alias(&self) -> String {
-        self.alias.clone()
-            .map(|alias| format!("{} : ", alias))
-            .unwrap_or("".to_string())
-    }
 }
 
-#[derive(Builder, Clone)]
+#[derive(Builder, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(remote = "Self"))]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct Edge {
-    name: String,
+    #[builder(setter(into))]
+    name: PredicateName,
     predicates: Vec<Predicate>,
     #[builder(setter(strip_option), default)]
     filter: Option<Condition>,
-    #[builder(setter(strip_option), default)]
-    alias: Option<String>
+    #[builder(setter(into, strip_option), default)]
+    alias: Option<Alias>,
+    #[builder(default)]
+    directives: Vec<Directive>
 }
 
 impl Edge {
-    fn get_alias(&self) -> String {
-        self.alias.clone()
-            .map(|alias| format!("{} : ", alias))
-            .unwrap_or("".to_string())
+    fn check_schema(&self, check: &mut SchemaCheck) {
+        check.edge(&self.name);
+        self.filter.iter().for_each(|filter| filter.check_schema(check, false));
+        self.predicates.iter().for_each(|predicate| predicate.check_schema(check));
+    }
+
+    fn collect_variables(&self, scope: &mut VariableScope) {
+        self.filter.iter().for_each(|filter| filter.collect_variables(scope));
+        self.predicates.iter().for_each(|predicate| predicate.collect_variables(scope));
+    }
+}
+
+// shared by the builder and deserializing
+fn validate_edge(directives: &[Directive], filter: Option<&Condition>) -> Result<(), String> {
+    if directives.contains(&Directive::IgnoreReflex) {
+        return Err("`@ignorereflex` is only allowed on a query block".to_string());
+    }
+
+    filter.map(validate_filter).unwrap_or(Ok(()))
+}
+
+#[cfg(feature = "serde")]
+impl_checked_serde!(Edge, |edge: &Edge| validate_edge(&edge.directives, edge.filter.as_ref()));
+
+impl EdgeBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let directives = self.directives.as_deref().unwrap_or_default();
+        validate_edge(directives, self.filter.as_ref().and_then(Option::as_ref))
+    }
+
+    pub fn directive(&mut self, directive: Directive) -> &mut Self {
+        self.directives.get_or_insert_with(Vec::new).push(directive);
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ExpandTarget {
+    All,
+    Types(Vec<TypeName>),
+    Val(ValueVar)
+}
+
+impl ToQueryString for ExpandTarget {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        match self {
+            ExpandTarget::All => w.write_str("_all_"),
+            ExpandTarget::Types(types) => write_separated(w, ", ", types, |w, name| name.write_query(w, config, depth)),
+            ExpandTarget::Val(variable) => write!(w, "val({})", variable.as_str())
+        }
+    }
+}
+
+// `expand(...)` selects every predicate of the node's types, with optional
+// sub-selections applied to the edges among them
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(remote = "Self"))]
+pub struct Expand {
+    target: ExpandTarget,
+    predicates: Vec<Predicate>,
+    filter: Option<Condition>
+}
+
+impl Expand {
+    pub fn new(target: ExpandTarget) -> Self {
+        Self {
+            target,
+            predicates: vec![],
+            filter: None
+        }
+    }
+
+    pub fn all() -> Self {
+        Self::new(ExpandTarget::All)
+    }
+
+    pub fn types(types: &[&str]) -> Self {
+        Self::new(ExpandTarget::Types(types.iter().map(|name| (*name).into()).collect()))
+    }
+
+    pub fn val(variable: impl Into<ValueVar>) -> Self {
+        Self::new(ExpandTarget::Val(variable.into()))
+    }
+
+    pub fn predicates(mut self, predicates: Vec<Predicate>) -> Self {
+        self.predicates = predicates;
+        self
+    }
+
+    // fails on a filter that never holds, like `EdgeBuilder::build`
+    pub fn filter(mut self, filter: Condition) -> Result<Self, String> {
+        validate_filter(&filter)?;
+        self.filter = Some(filter);
+        Ok(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl_checked_serde!(Expand, |expand: &Expand| expand.filter.as_ref().map(validate_filter).unwrap_or(Ok(())));
+
+impl Expand {
+    fn collect_variables(&self, scope: &mut VariableScope) {
+        if let ExpandTarget::Val(variable) = &self.target {
+            scope.use_variable(variable.as_str(), VariableKind::Value);
+        }
+
+        self.filter.iter().for_each(|filter| filter.collect_variables(scope));
+        self.predicates.iter().for_each(|predicate| predicate.collect_variables(scope));
+    }
+}
+
+impl ToQueryString for Expand {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        w.write_str("expand(")?;
+        self.target.write_query(w, config, depth)?;
+        w.write_char(')')?;
+
+        if let Some(filter) = &self.filter {
+            filter.write_filter(w, config, depth)?;
+        }
+
+        if self.predicates.is_empty() {
+            return Ok(());
+        }
+
+        w.write_char(' ')?;
+        config.write_block(w, depth, &self.predicates, |w, predicate| predicate.write_query(w, config, depth + 1))
+    }
+}
+
+fn write_alias(w: &mut impl Write, alias: &Option<Alias>) -> fmt::Result {
+    match alias {
+        Some(alias) => write!(w, "{} : ", alias.as_str()),
+        None => Ok(())
+    }
+}
+
+impl ToQueryString for Field {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        write_alias(w, &self.alias)?;
+        self.name.write_query(w, config, depth)
     }
 }
 
 impl ToQueryString for Edge {
-    fn to_query_string(&self) -> String {
-        let predicates = self.predicates.iter()
-            .map(|predicate| predicate.to_query_string())
-            .join("\n");
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        write_alias(w, &self.alias)?;
+        self.name.write_query(w, config, depth)?;
 
-        let filter = self.filter.clone()
-            .map(|filter| format!(" @filter({})", filter.to_query_string()))
-            .unwrap_or("".to_string());
+        if let Some(filter) = &self.filter {
+            filter.write_filter(w, config, depth)?;
+        }
 
-        format!("\
-        {alias}{name}{filter} {{\n\
-        {predicates}\n\
-        }}", alias = self.get_alias(), name = self.name, filter = filter, predicates = predicates.indent())
+        write_directives(w, &self.directives, config, depth)?;
+        w.write_char(' ')?;
+        config.write_block(w, depth, &self.predicates, |w, predicate| predicate.write_query(w, config, depth + 1))
     }
 }
 
 impl ToQueryString for Predicate {
-    fn to_query_string(&self) -> String {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
         match self {
             Predicate::Field(field) => {
-                format!("{alias}{name}", alias = field.get_alias(), name = field.name)
+                field.write_query(w, config, depth)
             },
             Predicate::Val(variable) => {
-                format!("{alias}val({name})", alias = variable.get_alias(), name = variable.name)
+                write_alias(w, &variable.alias)?;
+                w.write_str("val(")?;
+                variable.name.write_query(w, config, depth)?;
+                w.write_char(')')
             },
             Predicate::EdgeVariable(name, edge) => {
-                format!("{name} as {edge}", name = name, edge = edge.to_query_string())
+                name.write_query(w, config, depth)?;
+                w.write_str(" as ")?;
+                edge.write_query(w, config, depth)
             },
             Predicate::ScalarVariable(name, field) => {
-                format!("{name} as {field}", name = name, field = field.name)
+                name.write_query(w, config, depth)?;
+                w.write_str(" as ")?;
+                field.name.write_query(w, config, depth)
+            },
+            Predicate::UidVariable(name) => {
+                name.write_query(w, config, depth)?;
+                w.write_str(" as uid")
             },
             Predicate::Count(field) => {
-                format!("{alias}count({name})", alias = field.get_alias(), name = field.name)
+                write_alias(w, &field.alias)?;
+                w.write_str("count(")?;
+                field.name.write_query(w, config, depth)?;
+                w.write_char(')')
             },
             Predicate::Edge(edge) => {
-                format!("{edge}", edge = edge.to_query_string())
+                edge.write_query(w, config, depth)
+            },
+            Predicate::Expand(expand) => {
+                expand.write_query(w, config, depth)
             }
         }
     }
-}
\ No newline at end of file
+}
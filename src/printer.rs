@@ -0,0 +1,140 @@
+use std::fmt::{self, Write};
+use std::io;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PrettyConfig {
+    pub indent: String,
+    pub compact: bool,
+    pub trailing_newline: bool
+}
+
+impl PrettyConfig {
+    pub fn pretty() -> Self {
+        Self {
+            indent: "\t".to_string(),
+            compact: false,
+            trailing_newline: false
+        }
+    }
+
+    // everything on one line, for logs and request bodies
+    pub fn compact() -> Self {
+        Self {
+            compact: true,
+            ..Self::pretty()
+        }
+    }
+
+    pub fn indent(mut self, indent: &str) -> Self {
+        self.indent = indent.to_string();
+        self
+    }
+
+    pub fn trailing_newline(mut self, trailing_newline: bool) -> Self {
+        self.trailing_newline = trailing_newline;
+        self
+    }
+
+    fn write_indent(&self, w: &mut impl Write, depth: usize) -> fmt::Result {
+        for _ in 0..depth {
+            w.write_str(&self.indent)?;
+        }
+
+        Ok(())
+    }
+
+    // `{ ...items }` closing at `depth`, with one item per line unless compact
+    pub(crate) fn write_block<W, I, F>(&self, w: &mut W, depth: usize, items: I, mut write_item: F) -> fmt::Result
+        where W: Write, I: IntoIterator, F: FnMut(&mut W, I::Item) -> fmt::Result
+    {
+        let mut items = items.into_iter().peekable();
+
+        if items.peek().is_none() {
+            return w.write_str("{}");
+        }
+
+        w.write_char('{')?;
+
+        for item in items {
+            if self.compact {
+                w.write_char(' ')?;
+            } else {
+                w.write_char('\n')?;
+                self.write_indent(w, depth + 1)?;
+            }
+
+            write_item(w, item)?;
+        }
+
+        if self.compact {
+            w.write_str(" }")
+        } else {
+            w.write_char('\n')?;
+            self.write_indent(w, depth)?;
+            w.write_char('}')
+        }
+    }
+}
+
+impl Default for PrettyConfig {
+    fn default() -> Self {
+        Self::pretty()
+    }
+}
+
+pub(crate) fn write_separated<W, I, F>(w: &mut W, separator: &str, items: I, mut write_item: F) -> fmt::Result
+    where W: Write, I: IntoIterator, F: FnMut(&mut W, I::Item) -> fmt::Result
+{
+    for (index, item) in items.into_iter().enumerate() {
+        if index > 0 {
+            w.write_str(separator)?;
+        }
+
+        write_item(w, item)?;
+    }
+
+    Ok(())
+}
+
+// a JSON string literal, which is also how DQL quotes strings
+pub(crate) fn write_quoted(w: &mut impl Write, value: &str) -> fmt::Result {
+    w.write_char('"')?;
+
+    for c in value.chars() {
+        match c {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => w.write_str("\\r")?,
+            '\t' => w.write_str("\\t")?,
+            c if c.is_control() => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.write_char(c)?
+        }
+    }
+
+    w.write_char('"')
+}
+
+// lets the `fmt::Write` based renderers stream into an `io::Write`
+pub(crate) struct IoWriter<'a, W: io::Write> {
+    inner: &'a mut W,
+    pub(crate) error: Option<io::Error>
+}
+
+impl<'a, W: io::Write> IoWriter<'a, W> {
+    pub(crate) fn new(inner: &'a mut W) -> Self {
+        Self {
+            inner,
+            error: None
+        }
+    }
+}
+
+impl<W: io::Write> Write for IoWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
@@ -1,19 +1,280 @@
 use derive_builder::Builder;
-use crate::{ToQueryString, IndentedString};
-use crate::queryblock::QueryBlock;
-use itertools::Itertools;
+use crate::{ToQueryString, PrettyConfig};
+use crate::printer::{write_quoted, write_separated};
+use crate::queryblock::{QueryBlock, QueryBlockType};
+use crate::names::{NameError, NameGenerator, VarName, is_valid_literal, validate_identifier};
+use crate::response::BlockHandle;
+use crate::parser::{parse_query, ParseError};
+use crate::validation::{SchemaCheck, SchemaError, ValidationError, VariableScope};
+use crate::schema::Schema;
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
-#[derive(Builder, Clone)]
+#[derive(Builder, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(remote = "Self"))]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct Query {
-    query_blocks: Vec<QueryBlock>
+    query_blocks: Vec<QueryBlock>,
+    #[builder(setter(strip_option), default)]
+    name: Option<String>,
+    #[builder(default)]
+    params: Vec<QueryParam>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ParamType {
+    String,
+    Int,
+    Float,
+    Bool
+}
+
+impl ParamType {
+    // strings are quoted, everything else is spliced in and has to parse as its type
+    fn accepts_default(&self, default: &str) -> bool {
+        match self {
+            ParamType::String => true,
+            _ if !is_valid_literal(default) => false,
+            ParamType::Int => default.parse::<i64>().is_ok(),
+            ParamType::Float => default.parse::<f64>().map(f64::is_finite).unwrap_or(false),
+            ParamType::Bool => default == "true" || default == "false"
+        }
+    }
+}
+
+impl ToQueryString for ParamType {
+    fn write_query(&self, w: &mut impl Write, _config: &PrettyConfig, _depth: usize) -> fmt::Result {
+        match self {
+            ParamType::String => w.write_str("string"),
+            ParamType::Int => w.write_str("int"),
+            ParamType::Float => w.write_str("float"),
+            ParamType::Bool => w.write_str("bool")
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ParamError {
+    InvalidName(NameError),
+    InvalidDefault(ParamType, String)
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamError::InvalidName(err) => err.fmt(f),
+            ParamError::InvalidDefault(param_type, default) => write!(f, "invalid {} default: {:?}", param_type, default)
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+impl From<NameError> for ParamError {
+    fn from(err: NameError) -> Self {
+        ParamError::InvalidName(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(remote = "Self"))]
+pub struct QueryParam {
+    name: VarName,
+    param_type: ParamType,
+    default: Option<String>,
+    value: Option<String>
+}
+
+impl QueryParam {
+    // `new` and `default` panic on invalid input; `try_new` and `try_default` return the error
+    pub fn new(name: &str, param_type: ParamType) -> Self {
+        Self::try_new(name, param_type).unwrap()
+    }
+
+    pub fn try_new(name: &str, param_type: ParamType) -> Result<Self, ParamError> {
+        Ok(Self {
+            name: VarName::new(name)?,
+            param_type,
+            default: None,
+            value: None
+        })
+    }
+
+    pub fn default(self, default: &str) -> Self {
+        self.try_default(default).unwrap()
+    }
+
+    pub fn try_default(mut self, default: &str) -> Result<Self, ParamError> {
+        if !self.param_type.accepts_default(default) {
+            return Err(ParamError::InvalidDefault(self.param_type, default.to_string()));
+        }
+
+        self.default = Some(default.to_string());
+        Ok(self)
+    }
+
+    pub fn value(mut self, value: &str) -> Self {
+        self.value = Some(value.to_string());
+        self
+    }
+
+    pub fn get_name(&self) -> String {
+        self.name.as_str().to_string()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl_checked_serde!(QueryParam, |param: &QueryParam| match &param.default {
+    Some(default) if !param.param_type.accepts_default(default) => {
+        Err(ParamError::InvalidDefault(param.param_type.clone(), default.clone()))
+    },
+    _ => Ok(())
+});
+
+impl ToQueryString for QueryParam {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        w.write_char('$')?;
+        self.name.write_query(w, config, depth)?;
+        w.write_str(": ")?;
+        self.param_type.write_query(w, config, depth)?;
+
+        match (&self.default, &self.param_type) {
+            (Some(default), ParamType::String) => {
+                w.write_str(" = ")?;
+                write_quoted(w, default)
+            },
+            // `try_default` and deserializing already checked the default
+            (Some(default), _) => write!(w, " = {}", default),
+            (None, _) => Ok(())
+        }
+    }
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self {
+            query_blocks: vec![],
+            name: None,
+            params: vec![]
+        }
+    }
+
+    pub fn parse(query: &str) -> Result<Self, ParseError> {
+        parse_query(query)
+    }
+
+    pub fn add_param(&mut self, param: QueryParam) {
+        self.params.push(param);
+    }
+
+    // the query text along with the `vars` map dgraph expects next to it
+    pub fn to_query_with_vars(&self) -> (String, HashMap<String, String>) {
+        let vars = self.params.iter()
+            .filter_map(|param| param.value.as_ref().map(|value| (format!("${}", param.name.as_str()), value.clone())))
+            .collect();
+
+        (self.to_query_string(), vars)
+    }
+
+    // checks that every variable and parameter used is defined, every
+    // variable defined is used and that no name is defined twice
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut scope = VariableScope::default();
+        self.collect_variables(&mut scope)?;
+        scope.check()
+    }
+
+    // reports every predicate the schema doesn't know, doesn't allow to be used
+    // the way the query uses it or lacks the index a function needs
+    pub fn validate_against(&self, schema: &Schema) -> Result<(), Vec<SchemaError>> {
+        let mut check = SchemaCheck::new(schema);
+        self.query_blocks.iter().for_each(|query_block| query_block.check_schema(&mut check));
+
+        match check.errors.is_empty() {
+            true => Ok(()),
+            false => Err(check.errors)
+        }
+    }
+
+    pub(crate) fn collect_variables(&self, scope: &mut VariableScope) -> Result<(), ValidationError> {
+        let mut names = vec![];
+
+        for query_block in &self.query_blocks {
+            if let QueryBlockType::Query(name) = query_block.query_type() {
+                if names.contains(&name) {
+                    return Err(ValidationError::DuplicateBlockName(name.as_str().to_string()));
+                }
+
+                names.push(name);
+            }
+        }
+
+        self.params.iter().for_each(|param| scope.declare_param(param.name.as_str()));
+        self.query_blocks.iter().for_each(|query_block| query_block.collect_variables(scope));
+        Ok(())
+    }
+
+    pub fn add_block<T>(&mut self, block: QueryBlock) -> BlockHandle<T> {
+        self.query_blocks.push(block);
+        BlockHandle::new(self.query_blocks.len() - 1)
+    }
+
+    // unnamed blocks are numbered in order, skipping any name a block was given explicitly
+    pub fn block_names(&self) -> Vec<Option<String>> {
+        let mut generator = NameGenerator::new();
+
+        for query_block in &self.query_blocks {
+            if let QueryBlockType::Query(name) = query_block.query_type() {
+                generator.reserve(name.as_str());
+            }
+        }
+
+        self.query_blocks.iter()
+            .map(|query_block| match query_block.query_type() {
+                QueryBlockType::Query(name) => Some(name.as_str().to_string()),
+                QueryBlockType::Unnamed => Some(generator.next_name("query")),
+                QueryBlockType::Var => None
+            })
+            .collect()
+    }
+}
+
+impl QueryBuilder {
+    fn validate(&self) -> Result<(), String> {
+        match &self.name {
+            Some(Some(name)) => validate_identifier(name).map_err(|err| err.to_string()),
+            _ => Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl_checked_serde!(Query, |query: &Query| query.name.as_deref().map(validate_identifier).unwrap_or(Ok(())));
+
+impl Default for Query {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ToQueryString for Query {
-    fn to_query_string(&self) -> String {
-        let query_blocks = self.query_blocks.iter()
-            .map(|query_block| query_block.to_query_string().indent())
-            .join("\n");
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        if !self.params.is_empty() || self.name.is_some() {
+            // the builder and deserializing already checked the name
+            let name = self.name.as_deref().unwrap_or("q");
+            write!(w, "query {}(", name)?;
+            write_separated(w, ", ", &self.params, |w, param| param.write_query(w, config, depth))?;
+            w.write_str(") ")?;
+        }
+
+        let query_blocks = self.query_blocks.iter().zip(self.block_names());
 
-        format!("{{\n{query_blocks}\n}}", query_blocks = query_blocks)
+        config.write_block(w, depth, query_blocks, |w, (query_block, name)| match name {
+            Some(name) => query_block.write_named(&name, w, config, depth + 1),
+            None => query_block.write_query(w, config, depth + 1)
+        })
     }
 }
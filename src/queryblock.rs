@@ -1,112 +1,332 @@
 use derive_builder::Builder;
-use crate::predicate::Predicate;
-use crate::condition::Condition;
-use crate::{ToQueryString, IndentedString};
-use rand::random;
-use itertools::Itertools;
+use crate::predicate::{Predicate, UidVar, ValueVar, Variable};
+use crate::condition::{Condition, ConditionSubject, validate_filter};
+use crate::directive::{Directive, write_directives};
+use crate::{ToQueryString, PrettyConfig};
+use std::fmt::{self, Write};
+use crate::names::{NameError, NameGenerator, TypeName, Uid, VarName};
+use crate::printer::write_separated;
+use crate::validation::{SchemaCheck, VariableKind, VariableScope};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
 
-#[derive(Builder, Clone)]
+#[derive(Builder, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(remote = "Self"))]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct QueryBlock {
     query_type: QueryBlockType,
     predicates: Vec<Predicate>,
-    root_filter: Condition,
+    #[builder(setter(into))]
+    root_filter: RootFunction,
     #[builder(setter(strip_option), default)]
     filter: Option<Condition>,
     #[builder(setter(strip_option), default)]
     first: Option<i64>,
-    #[builder(setter(strip_option), default)]
-    variable: Option<String>,
+    #[builder(setter(into, strip_option), default)]
+    variable: Option<UidVar>,
     #[builder(default)]
     order: QueryOrder,
     #[builder(default)]
-    cascade: bool,
+    directives: Vec<Directive>,
+}
+
+// shared by the builder and deserializing
+fn validate_block(root: Option<&RootFunction>, filter: Option<&Condition>) -> Result<(), String> {
+    if let Some(filter) = filter {
+        validate_filter(filter)?;
+    }
+
+    root.map(RootFunction::validate).unwrap_or(Ok(()))
+}
+
+#[cfg(feature = "serde")]
+impl_checked_serde!(QueryBlock, |block: &QueryBlock| validate_block(Some(&block.root_filter), block.filter.as_ref()));
+
+impl QueryBlockBuilder {
+    fn validate(&self) -> Result<(), String> {
+        validate_block(self.root_filter.as_ref(), self.filter.as_ref().and_then(Option::as_ref))
+    }
+
+    pub fn directive(&mut self, directive: Directive) -> &mut Self {
+        self.directives.get_or_insert_with(Vec::new).push(directive);
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum UidRef {
+    Var(UidVar),
+    Uid(Uid)
+}
+
+impl From<UidVar> for UidRef {
+    fn from(variable: UidVar) -> Self {
+        UidRef::Var(variable)
+    }
+}
+
+impl From<&UidVar> for UidRef {
+    fn from(variable: &UidVar) -> Self {
+        UidRef::Var(variable.clone())
+    }
+}
+
+impl From<&str> for UidRef {
+    fn from(value: &str) -> Self {
+        if value.starts_with("0x") {
+            UidRef::Uid(value.into())
+        } else {
+            UidRef::Var(value.into())
+        }
+    }
+}
+
+impl ToQueryString for UidRef {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        match self {
+            UidRef::Var(variable) => w.write_str(variable.as_str()),
+            UidRef::Uid(uid) => uid.write_query(w, config, depth)
+        }
+    }
+}
+
+// the `func:` of a query block. dgraph only accepts a single function backed
+// by an index here (checked against a schema by `Query::validate_against`);
+// everything else belongs in `@filter`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(remote = "Self"))]
+pub enum RootFunction {
+    Condition(Condition),
+    Uid(Vec<UidRef>),
+    Type(TypeName)
+}
+
+impl RootFunction {
+    pub fn uid(refs: &[&str]) -> Self {
+        RootFunction::Uid(refs.iter().map(|&value| value.into()).collect())
+    }
+
+    pub fn uid_in(variables: &[&UidVar]) -> Self {
+        RootFunction::Uid(variables.iter().map(|&variable| variable.into()).collect())
+    }
+
+    pub fn of_type(name: &str) -> Self {
+        RootFunction::Type(name.into())
+    }
+
+    // checks the shape of the function only, the indexes it needs depend on the schema
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            RootFunction::Condition(condition) => match condition {
+                Condition::AND(..) | Condition::OR(..) | Condition::NOT(_) | Condition::ALL(_) | Condition::ANY(_) => {
+                    Err(format!("root functions can't combine conditions, use @filter instead: {}", condition))
+                },
+                Condition::EQ(subject, _)
+                | Condition::GE(subject, _)
+                | Condition::GT(subject, _)
+                | Condition::LE(subject, _)
+                | Condition::LT(subject, _) => match subject {
+                    ConditionSubject::Len(_) | ConditionSubject::Val(_) => {
+                        Err(format!("`{}` can't be used in a root function", subject))
+                    },
+                    _ => Ok(())
+                },
+                _ => Ok(())
+            },
+            RootFunction::Uid(refs) if refs.is_empty() => Err("uid() needs at least one uid or variable".to_string()),
+            RootFunction::Uid(_) | RootFunction::Type(_) => Ok(())
+        }
+    }
+}
+
+impl RootFunction {
+    pub(crate) fn collect_variables(&self, scope: &mut VariableScope) {
+        match self {
+            RootFunction::Condition(condition) => condition.collect_variables(scope),
+            RootFunction::Uid(refs) => refs.iter().for_each(|uid| {
+                if let UidRef::Var(variable) = uid {
+                    scope.use_variable(variable.as_str(), VariableKind::Uid);
+                }
+            }),
+            RootFunction::Type(_) => ()
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl_checked_serde!(RootFunction, RootFunction::validate);
+
+// `uid(...)` always becomes `RootFunction::Uid`, the same as a parsed root function
+impl From<Condition> for RootFunction {
+    fn from(condition: Condition) -> Self {
+        match condition {
+            Condition::UID(refs) => RootFunction::Uid(refs),
+            condition => RootFunction::Condition(condition)
+        }
+    }
+}
+
+impl ToQueryString for RootFunction {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        match self {
+            RootFunction::Condition(condition) => condition.write_query(w, config, depth),
+            RootFunction::Uid(refs) => {
+                w.write_str("uid(")?;
+                write_separated(w, ", ", refs, |w, uid| uid.write_query(w, config, depth))?;
+                w.write_char(')')
+            },
+            RootFunction::Type(name) => write!(w, "type({})", name.as_str())
+        }
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum QueryOrder {
+    #[default]
     None,
     ASC(Predicate),
     DESC(Predicate)
 }
 
-impl Default for QueryOrder {
-    fn default() -> Self {
-        QueryOrder::None
+impl QueryOrder {
+    pub fn asc_val(variable: &ValueVar) -> Self {
+        QueryOrder::ASC(Predicate::Val(Variable::from(variable.clone())))
+    }
+
+    pub fn desc_val(variable: &ValueVar) -> Self {
+        QueryOrder::DESC(Predicate::Val(Variable::from(variable.clone())))
     }
 }
 
 impl ToQueryString for QueryOrder {
-    fn to_query_string(&self) -> String {
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
         match self {
-            QueryOrder::None => format!(""),
-            QueryOrder::ASC(pred) => format!(", orderasc: {}", pred.to_query_string()),
-            QueryOrder::DESC(pred) => format!(", orderdesc: {}", pred.to_query_string())
+            QueryOrder::None => Ok(()),
+            QueryOrder::ASC(pred) => {
+                w.write_str(", orderasc: ")?;
+                pred.write_query(w, config, depth)
+            },
+            QueryOrder::DESC(pred) => {
+                w.write_str(", orderdesc: ")?;
+                pred.write_query(w, config, depth)
+            }
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum QueryBlockType {
-    Query(String),
+    Query(VarName),
+    Unnamed,
     Var
 }
 
 impl QueryBlockType {
+    // the name is assigned by the enclosing `Query` when it is rendered
     pub fn query() -> Self {
-        QueryBlockType::Query(Self::generate_name())
+        QueryBlockType::Unnamed
     }
 
-    pub fn var() -> Self {
-        QueryBlockType::Var
+    pub fn query_with(generator: &mut NameGenerator) -> Self {
+        Self::named(&generator.next_name("query"))
     }
 
-    fn generate_name() -> String {
-        format!("query_{}", random::<u128>())
+    // panics on an invalid name; use `try_named` to handle the error instead
+    pub fn named(name: &str) -> Self {
+        Self::try_named(name).unwrap()
+    }
+
+    pub fn try_named(name: &str) -> Result<Self, NameError> {
+        Ok(QueryBlockType::Query(VarName::new(name)?))
+    }
+
+    pub fn var() -> Self {
+        QueryBlockType::Var
     }
 }
 
 impl ToQueryString for QueryBlockType {
-    fn to_query_string(&self) -> String {
+    fn write_query(&self, w: &mut impl Write, _config: &PrettyConfig, _depth: usize) -> fmt::Result {
         match self {
-            QueryBlockType::Query(name) => name.clone(),
-            QueryBlockType::Var => "var".to_string()
+            QueryBlockType::Query(name) => w.write_str(name.as_str()),
+            QueryBlockType::Unnamed => w.write_str("query"),
+            QueryBlockType::Var => w.write_str("var")
+        }
+    }
+}
+
+impl QueryBlock {
+    pub fn query_type(&self) -> &QueryBlockType {
+        &self.query_type
+    }
+
+    pub fn variable(&self) -> Option<&UidVar> {
+        self.variable.as_ref()
+    }
+
+    pub(crate) fn check_schema(&self, check: &mut SchemaCheck) {
+        if let RootFunction::Condition(condition) = &self.root_filter {
+            condition.check_schema(check, true);
+        }
+
+        self.filter.iter().for_each(|filter| filter.check_schema(check, false));
+
+        if let QueryOrder::ASC(predicate) | QueryOrder::DESC(predicate) = &self.order {
+            predicate.check_schema(check);
+        }
+
+        self.predicates.iter().for_each(|predicate| predicate.check_schema(check));
+    }
+
+    pub(crate) fn collect_variables(&self, scope: &mut VariableScope) {
+        if let Some(variable) = &self.variable {
+            scope.define(variable.as_str(), VariableKind::Uid);
+        }
+
+        self.root_filter.collect_variables(scope);
+        self.filter.iter().for_each(|filter| filter.collect_variables(scope));
+
+        if let QueryOrder::ASC(predicate) | QueryOrder::DESC(predicate) = &self.order {
+            predicate.collect_variables(scope);
         }
+
+        self.predicates.iter().for_each(|predicate| predicate.collect_variables(scope));
+    }
+
+    pub(crate) fn write_named(&self, name: &str, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        if let Some(variable) = &self.variable {
+            variable.write_query(w, config, depth)?;
+            w.write_str(" as ")?;
+        }
+
+        write!(w, "{}(func: ", name)?;
+        self.root_filter.write_query(w, config, depth)?;
+        self.order.write_query(w, config, depth)?;
+
+        if let Some(first) = self.first {
+            write!(w, ", first: {}", first)?;
+        }
+        w.write_char(')')?;
+
+        if let Some(filter) = &self.filter {
+            filter.write_filter(w, config, depth)?;
+        }
+
+        write_directives(w, &self.directives, config, depth)?;
+
+        w.write_char(' ')?;
+        config.write_block(w, depth, &self.predicates, |w, predicate| predicate.write_query(w, config, depth + 1))
     }
 }
 
 impl ToQueryString for QueryBlock {
-    fn to_query_string(&self) -> String {
-        let query_block_inner = self.predicates.iter()
-            .map(|predicate| predicate.to_query_string())
-            .join("\n");
-
-        let first = self.first
-            .as_ref()
-            .map(|first| format!(", first: {}", first))
-            .unwrap_or(format!(""));
-
-        let filter = self.filter.clone()
-            .map(|filter| format!("@filter({})", filter.to_query_string()))
-            .unwrap_or("".to_string());
-
-        let cascade = if self.cascade { format!("@cascade") } else { format!("") };
-
-        let variable = self.variable.clone()
-            .map(|variable| format!("{} as ", variable))
-            .unwrap_or("".to_string());
-
-        format!("\
-        {variable}{name}(func: {root_filter}{order}{first}) {filter} {cascade} {{\
-        \n{query_block_inner}\
-        \n}}",
-                variable = variable,
-                name = self.query_type.to_query_string(),
-                root_filter = self.root_filter.to_query_string(),
-                order = self.order.to_query_string(),
-                first = first,
-                filter = filter,
-                cascade = cascade,
-                query_block_inner = query_block_inner.indent()
-        )
+    fn write_query(&self, w: &mut impl Write, config: &PrettyConfig, depth: usize) -> fmt::Result {
+        match &self.query_type {
+            QueryBlockType::Query(name) => self.write_named(name.as_str(), w, config, depth),
+            query_type => self.write_named(&query_type.to_query_string(), w, config, depth)
+        }
     }
 }
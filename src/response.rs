@@ -0,0 +1,120 @@
+use crate::query::Query;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+pub struct BlockHandle<T> {
+    index: usize,
+    _marker: PhantomData<fn() -> T>
+}
+
+impl<T> BlockHandle<T> {
+    pub(crate) fn new(index: usize) -> Self {
+        Self {
+            index,
+            _marker: PhantomData
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<T> Clone for BlockHandle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for BlockHandle<T> {}
+
+impl<T> PartialEq for BlockHandle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for BlockHandle<T> {}
+
+impl<T> Hash for BlockHandle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for BlockHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BlockHandle").field(&self.index).finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum ResponseError {
+    NotAnObject,
+    UnknownBlock(usize),
+    VarBlock(usize),
+    Deserialize(String, serde_json::Error)
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseError::NotAnObject => write!(f, "response data is not a JSON object"),
+            ResponseError::UnknownBlock(index) => write!(f, "query has no block at index {}", index),
+            ResponseError::VarBlock(index) => write!(f, "block at index {} is a var block and has no results", index),
+            ResponseError::Deserialize(name, err) => write!(f, "failed to deserialize results of block {}: {}", name, err)
+        }
+    }
+}
+
+impl std::error::Error for ResponseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ResponseError::Deserialize(_, err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    block_names: Vec<Option<String>>,
+    data: Map<String, Value>
+}
+
+impl Response {
+    // accepts either the full dgraph response (`{"data": {...}}`) or just its `data` object
+    pub fn new(query: &Query, response: Value) -> Result<Self, ResponseError> {
+        let data = match response {
+            Value::Object(mut object) => match object.remove("data") {
+                Some(Value::Object(data)) => data,
+                Some(_) => return Err(ResponseError::NotAnObject),
+                None => object
+            },
+            _ => return Err(ResponseError::NotAnObject)
+        };
+
+        Ok(Self {
+            block_names: query.block_names(),
+            data
+        })
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, handle: &BlockHandle<T>) -> Result<Vec<T>, ResponseError> {
+        let name = self.block_names.get(handle.index)
+            .ok_or(ResponseError::UnknownBlock(handle.index))?
+            .as_ref()
+            .ok_or(ResponseError::VarBlock(handle.index))?;
+
+        // dgraph omits blocks that matched nothing
+        match self.data.get(name) {
+            Some(results) => Vec::<T>::deserialize(results)
+                .map_err(|err| ResponseError::Deserialize(name.clone(), err)),
+            None => Ok(vec![])
+        }
+    }
+}
@@ -1,12 +1,17 @@
 use itertools::Itertools;
-use std::collections::HashMap;
-use std::iter::FromIterator;
+use std::fmt;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Schema {
     pub definitions: Vec<SchemaDefinition>
 }
 
+impl Default for Schema {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Schema {
     pub fn new() -> Self {
         Self {
@@ -22,27 +27,28 @@ impl Schema {
     pub fn add_definition_ref(&mut self, definition: SchemaDefinition) {
         self.definitions.push(definition);
     }
-}
 
-impl ToString for Schema {
-    fn to_string(&self) -> String {
-        let mut representation = self.definitions.iter()
-            .map(|def| def.to_string())
-            .join("\n");
-        representation += "\n\n";
+    pub fn predicate(&self, name: &str) -> Option<&PredicateDefinition> {
+        self.definitions.iter()
+            .flat_map(|def| &def.predicates)
+            .find(|pred| pred.name == name)
+    }
+}
 
-        let predicate_map: HashMap<&String, &PredicateDefinition> = HashMap::from_iter(self.definitions.iter()
-            .map(|def| &def.predicates)
-            .flatten()
-            .map(|pred| (&pred.name, pred)));
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}\n", self.definitions.iter().join("\n"))?;
 
-        representation += &predicate_map.iter().map(|(name, pred)| pred.to_string()).join("\n");
+        // predicates shared between types are only declared once
+        let mut predicates = self.definitions.iter()
+            .flat_map(|def| &def.predicates)
+            .unique_by(|pred| &pred.name);
 
-        representation
+        write!(f, "{}", predicates.join("\n"))
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct SchemaDefinition {
     name: String,
     predicates: Vec<PredicateDefinition>
@@ -65,11 +71,19 @@ impl SchemaDefinition {
         self.predicates.push(predicate);
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn predicates(&self) -> &[PredicateDefinition] {
+        &self.predicates
+    }
 }
 
-impl ToString for SchemaDefinition {
-    fn to_string(&self) -> String {
-        format!(
+impl fmt::Display for SchemaDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
             "type {type_name} {{\n\
                 \t{predicates}\n\
             }}",
@@ -78,12 +92,13 @@ impl ToString for SchemaDefinition {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct PredicateDefinition {
     name: String,
     predicate_type: PredicateType,
     indexing: Vec<Indexing>,
-    upsert: bool
+    upsert: bool,
+    reverse: bool
 }
 
 impl PredicateDefinition {
@@ -92,7 +107,8 @@ impl PredicateDefinition {
             name: name.to_string(),
             predicate_type,
             indexing: vec![],
-            upsert: false
+            upsert: false,
+            reverse: false
         }
     }
 
@@ -109,73 +125,129 @@ impl PredicateDefinition {
         self.upsert = true;
         self
     }
+
+    pub fn reverse(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn predicate_type(&self) -> &PredicateType {
+        &self.predicate_type
+    }
+
+    pub fn indexing(&self) -> &[Indexing] {
+        &self.indexing
+    }
+
+    pub fn is_reverse(&self) -> bool {
+        self.reverse
+    }
 }
 
-impl ToString for PredicateDefinition {
-    fn to_string(&self) -> String {
-        let mut index = format!("");
+impl fmt::Display for PredicateDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut index = String::new();
+        let (count, indexing): (Vec<_>, Vec<_>) = self.indexing.iter().partition(|&index| *index == Indexing::COUNT);
 
-        if !self.indexing.is_empty() {
-            index = format!(
-                " @index({})",
-                &self.indexing.iter().map(|pred| pred.to_string()).join(", ")
-            );
+        if !indexing.is_empty() {
+            index = format!(" @index({})", indexing.iter().join(", "));
         }
 
-        let upsert = if self.upsert {
-            format!(" @upsert")
-        } else {
-            format!("")
-        };
+        let count = if count.is_empty() { "" } else { " @count" };
+        let upsert = if self.upsert { " @upsert" } else { "" };
+        let reverse = if self.reverse { " @reverse" } else { "" };
 
-        format!(
-            "{name}: {ptype}{index}{upsert} .",
+        write!(
+            f,
+            "{name}: {ptype}{index}{count}{reverse}{upsert} .",
             name = &self.name,
-            ptype = self.predicate_type.to_string(),
+            ptype = self.predicate_type,
             index = index,
+            count = count,
+            reverse = reverse,
             upsert = upsert
         )
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum PredicateType {
     String,
     StringArray,
     UID,
     UIDArray,
     INT,
-    INTArray
+    INTArray,
+    FLOAT,
+    FLOATArray,
+    BOOL,
+    DATETIME
+}
+
+impl PredicateType {
+    pub fn is_uid(&self) -> bool {
+        matches!(self, PredicateType::UID | PredicateType::UIDArray)
+    }
+
+    pub fn is_list(&self) -> bool {
+        matches!(self, PredicateType::StringArray | PredicateType::UIDArray | PredicateType::INTArray | PredicateType::FLOATArray)
+    }
 }
 
-impl ToString for PredicateType {
-    fn to_string(&self) -> String {
+impl fmt::Display for PredicateType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            PredicateType::String => format!("string"),
-            PredicateType::StringArray => format!("[string]"),
-            PredicateType::UID => format!("uid"),
-            PredicateType::UIDArray => format!("[uid]"),
-            PredicateType::INT => format!("int"),
-            PredicateType::INTArray => format!("[int]")
+            PredicateType::String => write!(f, "string"),
+            PredicateType::StringArray => write!(f, "[string]"),
+            PredicateType::UID => write!(f, "uid"),
+            PredicateType::UIDArray => write!(f, "[uid]"),
+            PredicateType::INT => write!(f, "int"),
+            PredicateType::INTArray => write!(f, "[int]"),
+            PredicateType::FLOAT => write!(f, "float"),
+            PredicateType::FLOATArray => write!(f, "[float]"),
+            PredicateType::BOOL => write!(f, "bool"),
+            PredicateType::DATETIME => write!(f, "datetime")
         }
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Indexing {
     TERM,
     TRIGRAM,
     INT,
-    EXACT
+    EXACT,
+    HASH,
+    FLOAT,
+    BOOL,
+    // the datetime indexes, from coarsest to finest
+    YEAR,
+    MONTH,
+    DAY,
+    HOUR,
+    // rendered as `@count` rather than inside `@index(...)`
+    COUNT
 }
 
-impl ToString for Indexing {
-    fn to_string(&self) -> String {
+impl fmt::Display for Indexing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Indexing::TERM => format!("term"),
-            Indexing::TRIGRAM => format!("trigram"),
-            Indexing::INT => format!("int"),
-            Indexing::EXACT => format!("exact")
+            Indexing::TERM => write!(f, "term"),
+            Indexing::TRIGRAM => write!(f, "trigram"),
+            Indexing::INT => write!(f, "int"),
+            Indexing::EXACT => write!(f, "exact"),
+            Indexing::HASH => write!(f, "hash"),
+            Indexing::FLOAT => write!(f, "float"),
+            Indexing::BOOL => write!(f, "bool"),
+            Indexing::YEAR => write!(f, "year"),
+            Indexing::MONTH => write!(f, "month"),
+            Indexing::DAY => write!(f, "day"),
+            Indexing::HOUR => write!(f, "hour"),
+            Indexing::COUNT => write!(f, "count")
         }
     }
 }
\ No newline at end of file
@@ -1,13 +1,22 @@
-use crate::queryblock::QueryBlockType;
-use crate::condition::{Condition, ConditionValue};
-use crate::predicate::{Predicate, Field, Variable, Edge};
+use crate::queryblock::{QueryBlockType, QueryOrder, RootFunction};
+use crate::condition::{Condition, ConditionValue, FiniteFloat, ValueList};
+use crate::predicate::{Predicate, Field, Variable, Expand};
 use crate::{QueryBuilder, QueryBlockBuilder, EdgeBuilder};
-use crate::ToQueryString;
+use crate::{ToQueryString, PrettyConfig};
 use crate::MutationBuilder;
-use serde_json;
-use crate::mutation::{MutationUnit, MutationUID, MutationPredicateValue, Mutation};
+use crate::mutation::{MutationUnit, MutationUID, MutationPredicateValue, ScalarValue};
 use crate::upsert::{Upsert, UpsertBlock};
 use crate::schema::{Schema, SchemaDefinition, PredicateDefinition, PredicateType, Indexing};
+use crate::names::{PredicateName, VarName, Alias, Literal, TypeName, Uid};
+use crate::query::{Query, QueryParam, ParamType, ParamError};
+use crate::names::{clear_default_generator, reset_default_generator, set_default_generator, NameGenerator};
+use crate::response::{Response, BlockHandle, ResponseError};
+use crate::dsl::{pred, has, count, len, val};
+use crate::directive::Directive;
+use crate::validation::{SchemaError, ValidationError};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
 
 #[test]
 fn create_query() {
@@ -15,13 +24,13 @@ fn create_query() {
         .query_blocks(vec![
             QueryBlockBuilder::default()
                 .query_type(QueryBlockType::var())
-                .root_filter(Condition::HAS("last_seen".to_string()))
+                .root_filter(Condition::HAS("last_seen".into()))
                 .predicates(vec![
                     Predicate::Field(Field::new("uid")),
-                    Predicate::ScalarVariable("TIME".to_string(), Field::new("last_seen")),
-                    Predicate::EdgeVariable("IMPORTANT_EDGES".to_string(), EdgeBuilder::default()
+                    Predicate::ScalarVariable("TIME".into(), Field::new("last_seen")),
+                    Predicate::EdgeVariable("IMPORTANT_EDGES".into(), EdgeBuilder::default()
                         .name("connected_elements".to_string())
-                        .filter(Condition::REGEXP("a_predicate".to_string(), ConditionValue::Regexp("[aA].+".to_string())))
+                        .filter(Condition::REGEXP("a_predicate".into(), ConditionValue::Regexp("[aA].+".to_string())))
                         .alias("some_alias".to_string())
                         .predicates(vec![
                             Predicate::Field(Field::new("uid")),
@@ -31,8 +40,8 @@ fn create_query() {
                 .build().unwrap(),
             QueryBlockBuilder::default()
                 .query_type(QueryBlockType::query())
-                .filter(Condition::GT("last_seen".to_string(), ConditionValue::Literal("1234".to_string())))
-                .root_filter(Condition::EQ("node_key".to_string(), ConditionValue::String("fake_node_key".to_string())))
+                .filter(Condition::GT("last_seen".into(), ConditionValue::Literal("1234".into())))
+                .root_filter(Condition::EQ("node_key".into(), ConditionValue::String("fake_node_key".to_string())))
                 .predicates(vec![
                     Predicate::Field(Field::new("uid")),
                     Predicate::Field(Field::new("node_key")),
@@ -45,7 +54,7 @@ fn create_query() {
                             Predicate::Field(Field::new("org_name")),
                             Predicate::Edge(EdgeBuilder::default()
                                 .name("nested_edge".to_string())
-                                .filter(Condition::EQ("inner_predicate".to_string(), ConditionValue::String("A string".to_string())))
+                                .filter(Condition::EQ("inner_predicate".into(), ConditionValue::String("A string".to_string())))
                                 .predicates(vec![
                                     Predicate::Field(Field::new("uid")),
                                     Predicate::Field(Field::new("dgraph.type").alias("dgraph_type")),
@@ -57,7 +66,7 @@ fn create_query() {
                 .build().unwrap(),
             QueryBlockBuilder::default()
                 .query_type(QueryBlockType::query())
-                .root_filter(Condition::EQ("last_seen".to_string(), ConditionValue::Val(Variable::new("TIME"))))
+                .root_filter(Condition::EQ("last_seen".into(), ConditionValue::Val("TIME".into())))
                 .predicates(vec![
                     Predicate::Field(Field::new("uid")),
                     Predicate::Field(Field::new("last_seen")),
@@ -104,9 +113,9 @@ fn create_upsert() {
             QueryBlockBuilder::default()
                 .query_type(QueryBlockType::query())
                 .root_filter(Condition::has("pred"))
-                .filter(Condition::EQ("pred".to_string(), ConditionValue::string("Some Value2")))
+                .filter(Condition::EQ("pred".into(), ConditionValue::string("Some Value2")))
                 .predicates(vec![
-                    Predicate::ScalarVariable("SUBJECT_NODE".to_string(), Field::new("uid"))
+                    Predicate::UidVariable("SUBJECT_NODE".into())
                 ])
                 .build().unwrap(),
         ]).build().unwrap();
@@ -132,16 +141,16 @@ fn create_edge_upsert() {
         .query_blocks(vec![
             QueryBlockBuilder::default()
                 .query_type(QueryBlockType::Var)
-                .root_filter(Condition::EQ(format!("node_key"), ConditionValue::String(node_key_1)))
+                .root_filter(Condition::EQ("node_key".into(), ConditionValue::String(node_key_1)))
                 .predicates(vec![
-                    Predicate::ScalarVariable("SUBJECT_NODE".to_string(), Field::new("uid"))
+                    Predicate::UidVariable("SUBJECT_NODE".into())
                 ])
                 .build().unwrap(),
             QueryBlockBuilder::default()
                 .query_type(QueryBlockType::Var)
-                .root_filter(Condition::EQ(format!("node_key"), ConditionValue::String(node_key_2)))
+                .root_filter(Condition::EQ("node_key".into(), ConditionValue::String(node_key_2)))
                 .predicates(vec![
-                    Predicate::ScalarVariable("SUBJECT_NODE_2".to_string(), Field::new("uid"))
+                    Predicate::UidVariable("SUBJECT_NODE_2".into())
                 ])
                 .build().unwrap(),
         ]).build().unwrap();
@@ -163,7 +172,7 @@ fn create_edge_upsert() {
 
 #[test]
 fn generate_schema() {
-    let mut schema = Schema::new()
+    let schema = Schema::new()
         .add_definition(SchemaDefinition::new("Organization")
             .add_predicate(PredicateDefinition::new("first_seen", PredicateType::INT)
                 .add_index(Indexing::INT))
@@ -175,5 +184,1174 @@ fn generate_schema() {
             .add_predicate(PredicateDefinition::new("org_name", PredicateType::String)
                 .add_index(Indexing::TRIGRAM)));
 
-    println!("{}", schema.to_string());
-}
\ No newline at end of file
+    println!("{}", schema);
+}
+
+#[test]
+fn decode_response() {
+    #[derive(Deserialize)]
+    struct Org {
+        org_name: String
+    }
+
+    let mut query = Query::new();
+    query.add_block::<()>(QueryBlockBuilder::default()
+        .query_type(QueryBlockType::var())
+        .root_filter(Condition::has("org_name"))
+        .predicates(vec![
+            Predicate::UidVariable("ORGS".into())
+        ])
+        .build().unwrap());
+    let orgs = query.add_block::<Org>(QueryBlockBuilder::default()
+        .query_type(QueryBlockType::query())
+        .root_filter(Condition::uid("ORGS"))
+        .predicates(vec![
+            Predicate::Field(Field::new("org_name"))
+        ])
+        .build().unwrap());
+    let empty = query.add_block::<Org>(QueryBlockBuilder::default()
+        .query_type(QueryBlockType::query())
+        .root_filter(Condition::has("missing"))
+        .predicates(vec![
+            Predicate::Field(Field::new("org_name"))
+        ])
+        .build().unwrap());
+
+    let response = Response::new(&query, json!({
+        "data": {
+            "query_0": [{"org_name": "a"}, {"org_name": "b"}]
+        }
+    })).unwrap();
+
+    let orgs = response.get(&orgs).unwrap();
+    assert_eq!(orgs.iter().map(|org| org.org_name.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+    assert!(response.get(&empty).unwrap().is_empty());
+    assert!(matches!(response.get(&BlockHandle::<Org>::new(0)), Err(ResponseError::VarBlock(0))));
+}
+
+#[test]
+fn deterministic_names() {
+    let build_query = || QueryBuilder::default()
+        .query_blocks(vec![
+            QueryBlockBuilder::default()
+                .query_type(QueryBlockType::query())
+                .root_filter(Condition::has("name"))
+                .predicates(vec![Predicate::Field(Field::new("uid"))])
+                .build().unwrap(),
+            QueryBlockBuilder::default()
+                .query_type(QueryBlockType::named("query_0"))
+                .root_filter(Condition::has("name"))
+                .predicates(vec![Predicate::Field(Field::new("uid"))])
+                .build().unwrap()
+        ])
+        .build().unwrap();
+
+    assert_eq!(build_query().to_query_string(), build_query().to_query_string());
+    assert_eq!(build_query().block_names(), vec![Some("query_1".to_string()), Some("query_0".to_string())]);
+
+    assert!(QueryBlockType::try_named("me").is_ok());
+    assert!(QueryBlockType::try_named("0me").is_err());
+    assert!(QueryBlockType::try_named("me(func: has(secret)) { secret } me").is_err());
+
+    reset_default_generator(0);
+    assert_eq!(Variable::random().get_name(), "var_0");
+    assert_eq!(serde_json::to_value(MutationUID::placeholder()).unwrap(), json!({"uid": "_:node_1"}));
+}
+
+#[test]
+fn name_generators() {
+    let mut first = NameGenerator::seeded(42);
+    let mut second = NameGenerator::seeded(42);
+    let names = (0..3).map(|_| first.next_name("var")).collect::<Vec<_>>();
+    assert_eq!(names, (0..3).map(|_| second.next_name("var")).collect::<Vec<_>>());
+    assert_ne!(names[0], names[1]);
+
+    let mut custom = NameGenerator::custom(|prefix, n| format!("{}{}", &prefix[..1], n));
+    custom.reserve("n1");
+    assert_eq!(MutationUID::placeholder_with(&mut custom).get_uid(), "_:n0");
+    assert_eq!(MutationUID::placeholder_with(&mut custom).get_uid(), "_:n2");
+    assert_eq!(Variable::random_with(&mut custom).get_name(), "v3");
+
+    // names that aren't identifiers are skipped rather than used
+    let mut dashed = NameGenerator::custom(|prefix, n| match n % 2 {
+        0 => format!("{}-{}", prefix, n),
+        _ => format!("{}{}", prefix, n)
+    });
+    assert_eq!(Variable::random_with(&mut dashed).get_name(), "var1");
+    set_default_generator(dashed);
+    assert_eq!(Variable::random().get_name(), "var3");
+
+    set_default_generator(NameGenerator::custom(|_, n| format!("b{}", n)));
+    assert_eq!(MutationUID::placeholder().get_uid(), "_:b0");
+    clear_default_generator();
+
+    // without a generator of their own, threads share one counter
+    let here = Variable::random().get_name();
+    let there = std::thread::spawn(|| Variable::random().get_name()).join().unwrap();
+    assert_ne!(here, there);
+}
+
+#[test]
+#[should_panic(expected = "No valid unreserved name")]
+fn name_generator_gives_up() {
+    let mut stuck = NameGenerator::custom(|_, _| "taken".to_string());
+    stuck.reserve("taken");
+    stuck.next_name("var");
+}
+
+#[test]
+fn query_params() {
+    let query = QueryBuilder::default()
+        .name("people".to_string())
+        .params(vec![
+            QueryParam::new("name", ParamType::String).value("bob\") { secret }"),
+            QueryParam::new("limit", ParamType::Int).default("10")
+        ])
+        .query_blocks(vec![
+            QueryBlockBuilder::default()
+                .query_type(QueryBlockType::named("people"))
+                .root_filter(Condition::EQ("name".into(), ConditionValue::param("name")))
+                .predicates(vec![Predicate::Field(Field::new("uid"))])
+                .build().unwrap()
+        ])
+        .build().unwrap();
+
+    let (text, vars) = query.to_query_with_vars();
+    assert!(text.starts_with("query people($name: string, $limit: int = 10) {\n\tpeople(func: eq(name, $name))"));
+    assert_eq!(vars.len(), 1);
+    assert_eq!(vars["$name"], "bob\") { secret }");
+
+    assert!(QueryBuilder::default().name("not valid".to_string()).query_blocks(vec![]).build().is_err());
+}
+
+#[test]
+fn validate_query_params() {
+    assert!(matches!(QueryParam::try_new("a b", ParamType::Int), Err(ParamError::InvalidName(_))));
+
+    let param = |param_type| QueryParam::try_new("p", param_type).unwrap();
+    assert!(param(ParamType::String).try_default("a\" } { secret").is_ok());
+    assert!(param(ParamType::Int).try_default("-10").is_ok());
+    assert!(param(ParamType::Float).try_default("1.5").is_ok());
+    assert!(param(ParamType::Bool).try_default("true").is_ok());
+    assert_eq!(
+        param(ParamType::Int).try_default("10) { secret }"),
+        Err(ParamError::InvalidDefault(ParamType::Int, "10) { secret }".to_string()))
+    );
+    assert!(param(ParamType::Int).try_default("1.5").is_err());
+    assert!(param(ParamType::Float).try_default("inf").is_err());
+    assert!(param(ParamType::Bool).try_default("yes").is_err());
+
+    assert!(Query::parse("query q($a: int = abc) { q(func: has(name)) { uid } }").is_err());
+}
+
+#[test]
+fn escape_identifiers() {
+    assert_eq!(PredicateName::from("dgraph.type").to_query_string(), "dgraph.type");
+    assert_eq!(PredicateName::from("~friend").to_query_string(), "~friend");
+    assert_eq!(PredicateName::from("name@en").to_query_string(), "name@en");
+    assert_eq!(PredicateName::from("http://schema.org/name").to_query_string(), "<http://schema.org/name>");
+    assert_eq!(PredicateName::from("~has(x)").to_query_string(), "~<has(x)>");
+    assert!(PredicateName::new("name> secret").is_err());
+    assert!(PredicateName::new("").is_err());
+    assert!(VarName::new("a), has(secret").is_err());
+    assert!(Alias::new("a b").is_err());
+
+    let condition = Condition::REGEXP("name".into(), ConditionValue::regexp("a/) OR has(secret) OR regexp(name, /b"));
+    assert_eq!(condition.to_query_string(), "regexp(name, /a\\/) OR has(secret) OR regexp(name, \\/b/)");
+    assert_eq!(Condition::REGEXP("name".into(), ConditionValue::regexp("a\\/b")).to_query_string(), "regexp(name, /a\\/b/)");
+
+    let trailing = Condition::REGEXP("a".into(), ConditionValue::regexp("x\\"))
+        | Condition::REGEXP("b".into(), ConditionValue::regexp(") OR has(secret) OR eq(c, \""));
+    assert_eq!(trailing.to_query_string(), "regexp(a, /x\\\\/) OR regexp(b, /) OR has(secret) OR eq(c, \"/)");
+    assert_eq!(Condition::EQ(Condition::len("NODES"), ConditionValue::literal_int(0)).to_query_string(), "eq(len(NODES), 0)");
+}
+
+#[test]
+#[should_panic]
+fn reject_invalid_literal() {
+    Condition::GT("age".into(), ConditionValue::literal("1) OR has(secret")).to_query_string();
+}
+
+#[test]
+fn parse_query() {
+    let dql = r#"
+        query people($name: string = "bob", $limit: int) {
+            # uids of everyone we care about
+            PEOPLE as var(func: regexp(name, /^a\/b.*$/)) @filter(has(email) AND NOT (eq(age, 1) OR lt(len(PEOPLE), 3))) {
+                AGE as age
+                FRIENDS as best : friend @filter(eq(name, ["a", "b"])) {
+                    uid
+                }
+            }
+
+            people(func: uid(PEOPLE, 0x1f), orderdesc: val(AGE), first: 10) @filter(eq(name, $name)) @cascade {
+                uid
+                <http://schema.org/name>
+                ~<http://schema.org/knows> {
+                    uid
+                }
+                years : val(AGE)
+                friends : count(friend)
+                name@en
+            }
+        }
+    "#;
+
+    let query = Query::parse(dql).unwrap();
+    let rendered = query.to_query_string();
+    assert_eq!(Query::parse(&rendered).unwrap(), query);
+    assert!(rendered.contains("regexp(name, /^a\\/b.*$/)"));
+    assert!(rendered.contains("people(func: uid(PEOPLE, 0x1f), orderdesc: val(AGE), first: 10)"));
+    assert!(rendered.contains("FRIENDS as best : friend @filter(eq(name, [\"a\", \"b\"]))"));
+    assert!(rendered.contains("~<http://schema.org/knows> {"));
+
+    let error = match Query::parse("{\n  q(func: has(name)) {\n    uid\n    count(\n  }\n}") {
+        Err(error) => error,
+        Ok(_) => panic!("expected a parse error")
+    };
+    assert_eq!((error.line, error.column), (5, 3));
+    assert_eq!(error.message, "expected a predicate name, found `}`");
+
+    assert!(Query::parse("{ q(func: has(name)) @filter(eq(a, 1) OR has(b)) { uid }").is_err());
+    assert!(Query::parse("{ q(func: has(name), offset: 1) { uid } }").is_err());
+    assert!(Query::parse("{ q(func: eq(name, 1) OR has(b)) { uid } }").is_err());
+}
+
+#[test]
+fn printer_modes() {
+    let query = Query::parse("{ people(func: has(name)) @cascade { uid friend @filter(has(age)) { name } } }").unwrap();
+
+    assert_eq!(query.to_query_string(), "\
+{
+\tpeople(func: has(name)) @cascade {
+\t\tuid
+\t\tfriend @filter(has(age)) {
+\t\t\tname
+\t\t}
+\t}
+}");
+    assert_eq!(query.to_query_string_with(&PrettyConfig::pretty().indent("  ").trailing_newline(true)), "\
+{
+  people(func: has(name)) @cascade {
+    uid
+    friend @filter(has(age)) {
+      name
+    }
+  }
+}
+");
+    assert_eq!(
+        query.to_query_string_with(&PrettyConfig::compact()),
+        "{ people(func: has(name)) @cascade { uid friend @filter(has(age)) { name } } }"
+    );
+}
+
+#[test]
+fn stream_rendering() {
+    let query = Query::parse("{ people(func: has(name)) { uid friend { name } } }").unwrap();
+
+    let mut bytes = vec![];
+    query.write_query_io(&mut bytes, &PrettyConfig::pretty().trailing_newline(true)).unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), query.to_query_string() + "\n");
+
+    let mut nested = String::from("\t");
+    query.write_query(&mut nested, &PrettyConfig::pretty(), 1).unwrap();
+    assert_eq!(nested, format!("\t{}", query.to_query_string().replace('\n', "\n\t")));
+}
+
+#[test]
+fn compare_and_display() {
+    let dql = "{ people(func: has(name)) @filter(eq(age, 30)) { uid friend { name } } }";
+    let first = Query::parse(dql).unwrap();
+    let second = Query::parse(dql).unwrap();
+    let other = Query::parse("{ people(func: has(name)) { uid } }").unwrap();
+
+    assert_eq!(first, second);
+    assert_ne!(first, other);
+
+    let cache: HashSet<Query> = vec![first.clone(), second, other].into_iter().collect();
+    assert_eq!(cache.len(), 2);
+    assert_eq!(format!("{}", first), first.to_query_string());
+    assert_eq!(format!("{}", Field::new("dgraph.type").alias("type")), "type : dgraph.type");
+    assert!(format!("{:?}", Condition::has("name")).contains("HAS"));
+
+    let schema = Schema::new()
+        .add_definition(SchemaDefinition::new("Person")
+            .add_predicate(PredicateDefinition::new("name", PredicateType::String).add_index(Indexing::EXACT))
+            .add_predicate(PredicateDefinition::new("age", PredicateType::INT)))
+        .add_definition(SchemaDefinition::new("Pet")
+            .add_predicate(PredicateDefinition::new("name", PredicateType::String).add_index(Indexing::EXACT)));
+    assert_eq!(schema.to_string(), "\
+type Person {
+\tname
+\tage
+}
+type Pet {
+\tname
+}
+
+name: string @index(exact) .
+age: int .");
+}
+
+#[test]
+fn condition_dsl() {
+    let condition = pred("age").ge(18) & pred("name").eq("bob") | !has("banned");
+    assert_eq!(condition.to_query_string(), r#"(ge(age, 18) AND eq(name, "bob")) OR not has(banned)"#);
+
+    assert_eq!(condition, Condition::OR(
+        Box::new(Condition::AND(
+            Box::new(Condition::GE("age".into(), ConditionValue::literal_int(18))),
+            Box::new(Condition::EQ("name".into(), ConditionValue::string("bob")))
+        )),
+        Box::new(Condition::NOT(Box::new(Condition::has("banned"))))
+    ));
+
+    assert_eq!(len("friends").gt(2).to_query_string(), "gt(len(friends), 2)");
+    assert_eq!(pred("name").eq(vec!["a", "b"]).to_query_string(), r#"eq(name, ["a", "b"])"#);
+    assert_eq!(pred("score").eq(Variable::new("total")).to_query_string(), "eq(score, val(total))");
+
+    let all = Condition::and_all(vec![has("a"), has("b"), has("c")]).unwrap();
+    assert_eq!(all.to_query_string(), "has(a) AND has(b) AND has(c)");
+    assert_eq!(Condition::or_any(vec![has("a")]), Some(has("a")));
+    assert_eq!(Condition::or_any(vec![]), None);
+}
+
+#[test]
+fn simplify_conditions() {
+    let condition = (has("a") & has("b")) & (has("c") & has("a")) & !!has("d");
+    assert_eq!(condition.clone().simplify(), Condition::ALL(vec![has("a"), has("b"), has("c"), has("d")]));
+    assert_eq!(condition.simplify().to_query_string(), "has(a) AND has(b) AND has(c) AND has(d)");
+
+    let condition = (has("a") | has("b")) & !(has("c") | has("d") & has("e"));
+    assert_eq!(condition.to_query_string(), "(has(a) OR has(b)) AND not (has(c) OR (has(d) AND has(e)))");
+
+    assert_eq!((has("a") & Condition::TRUE).simplify(), has("a"));
+    assert_eq!((has("a") & Condition::FALSE).simplify(), Condition::FALSE);
+    assert_eq!((has("a") | !Condition::FALSE).simplify(), Condition::TRUE);
+    assert_eq!((has("a") | has("a")).simplify(), has("a"));
+
+    let block = QueryBlockBuilder::default()
+        .query_type(QueryBlockType::named("q"))
+        .root_filter(has("name"))
+        .filter((has("a") | Condition::TRUE).simplify())
+        .predicates(vec![Predicate::Field(Field::new("uid"))])
+        .build().unwrap();
+    assert_eq!(block.to_query_string_with(&PrettyConfig::compact()), "q(func: has(name)) { uid }");
+
+    let rendered = (has("a") & (has("b") | !has("c"))).to_query_string();
+    let query = Query::parse(&format!("{{ q(func: has(x)) @filter({}) {{ uid }} }}", rendered)).unwrap();
+    assert!(query.to_query_string().contains(&format!("@filter({})", rendered)));
+}
+
+#[test]
+fn render_constant_conditions() {
+    assert_eq!((has("a") & Condition::TRUE).to_query_string(), "has(a)");
+    assert_eq!((has("a") | Condition::FALSE & has("b")).to_query_string(), "has(a)");
+    assert_eq!((has("a") & (has("b") | !Condition::TRUE)).to_query_string(), "has(a) AND has(b)");
+    assert_eq!((has("a") | Condition::TRUE).to_query_string(), "has(a) OR not has(a)");
+    assert_eq!((!(has("a") & Condition::FALSE)).to_query_string(), "has(a) OR not has(a)");
+    assert_eq!((has("b") & (has("a") & Condition::FALSE)).to_query_string(), "has(b) AND not has(b)");
+
+    let edge = EdgeBuilder::default()
+        .name("friends")
+        .filter(has("a") & Condition::TRUE)
+        .predicates(vec![Predicate::Field(Field::new("uid"))])
+        .build().unwrap();
+    assert_eq!(edge.to_query_string_with(&PrettyConfig::compact()), "friends @filter(has(a)) { uid }");
+
+    // a filter that never holds is rejected rather than left to fail at render time
+    let block = QueryBlockBuilder::default()
+        .query_type(QueryBlockType::named("q"))
+        .root_filter(has("name"))
+        .filter((has("a") & Condition::FALSE).simplify())
+        .predicates(vec![Predicate::Field(Field::new("uid"))])
+        .build();
+    assert!(block.unwrap_err().contains("never holds"));
+
+    let edge = EdgeBuilder::default()
+        .name("friends")
+        .filter(has("a") & !Condition::TRUE)
+        .predicates(vec![Predicate::Field(Field::new("uid"))])
+        .build();
+    assert!(edge.is_err());
+
+    assert!(Expand::all().filter(Condition::FALSE).is_err());
+    let expand = Expand::all().filter(Condition::TRUE).unwrap();
+    assert_eq!(expand.to_query_string(), "expand(_all_)");
+
+    // an upsert condition that always holds is left out, one that never does is an error
+    let upsert = UpsertBlock::new(MutationBuilder::default().build().unwrap()).cond(Condition::TRUE);
+    assert!(serde_json::to_value(&upsert).unwrap().get("cond").is_none());
+    let upsert = UpsertBlock::new(MutationBuilder::default().build().unwrap()).cond(Condition::FALSE);
+    assert!(serde_json::to_value(&upsert).is_err());
+}
+
+#[test]
+fn typed_values() {
+    assert_eq!(pred("score").ge(2.5).to_query_string(), "ge(score, 2.5)");
+    assert_eq!(pred("active").eq(true).to_query_string(), "eq(active, true)");
+    assert_eq!(pred("owner").eq(ConditionValue::uid("0x2a")).to_query_string(), "eq(owner, 0x2a)");
+
+    let mixed = ConditionValue::list(vec!["a".into(), 1.into(), 0.5.into(), false.into()]);
+    assert_eq!(pred("tag").eq(mixed).to_query_string(), r#"eq(tag, ["a", 1, 0.5, false])"#);
+
+    let mut set = HashSet::new();
+    set.insert(ConditionValue::float(0.0));
+    assert!(set.contains(&ConditionValue::float(-0.0)));
+    assert_ne!(ConditionValue::float(1.0), ConditionValue::literal("1"));
+}
+
+#[test]
+fn validated_values() {
+    let date = pred("d").ge(ConditionValue::literal("\"2020-01-01\""));
+    assert_eq!(date.to_query_string(), "ge(d, \"2020-01-01\")");
+    assert!(Literal::new("\"a\\\"b\"").is_ok());
+    assert!(Literal::new("\"a\") OR has(secret) OR eq(b, \"").is_err());
+    assert!(Literal::new("\"a\\\"").is_err());
+
+    assert!(Uid::new("0x1f").is_ok());
+    assert!(Uid::new("a b").is_err());
+    assert!(TypeName::new("a b").is_err());
+    assert!(FiniteFloat::new(f64::NAN).is_none());
+    assert!(ValueList::new(vec![ConditionValue::list(vec![1.into()])]).is_err());
+
+    assert_eq!(Condition::uid("0x1, PEOPLE").to_query_string(), "uid(0x1, PEOPLE)");
+    assert!(std::panic::catch_unwind(|| Condition::uid("a b")).is_err());
+
+    // a constant without any function in it is only ever rendered on its own
+    assert_eq!(Condition::TRUE.to_query_string(), "true");
+    assert_eq!((!Condition::TRUE).to_query_string(), "false");
+}
+
+#[test]
+#[should_panic(expected = "Invalid float")]
+fn reject_non_finite_float() {
+    pred("score").ge(f64::NAN).to_query_string();
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn datetime_values() {
+    use chrono::{FixedOffset, TimeZone, Utc};
+
+    let time = Utc.with_ymd_and_hms(2020, 5, 17, 8, 30, 0).unwrap();
+    assert_eq!(pred("created").ge(time).to_query_string(), r#"ge(created, "2020-05-17T08:30:00Z")"#);
+
+    let offset = FixedOffset::east_opt(2 * 3600).unwrap().with_ymd_and_hms(2020, 5, 17, 10, 30, 0).unwrap();
+    assert_eq!(pred("created").ge(offset).to_query_string(), r#"ge(created, "2020-05-17T10:30:00+02:00")"#);
+    assert_eq!(ConditionValue::datetime(offset), ConditionValue::datetime(time));
+}
+
+#[test]
+fn condition_subjects() {
+    assert_eq!(count("friend").ge(5).to_query_string(), "ge(count(friend), 5)");
+    assert_eq!(count("~http://schema.org/knows").gt(1).to_query_string(), "gt(count(~<http://schema.org/knows>), 1)");
+    assert_eq!(val("AGE").lt(30).to_query_string(), "lt(val(AGE), 30)");
+    assert_eq!(Condition::EQ("name".into(), "bob".into()), pred("name").eq("bob"));
+
+    let block = QueryBlockBuilder::default()
+        .query_type(QueryBlockType::named("popular"))
+        .root_filter(count("friend").ge(100))
+        .filter(len("FRIENDS").gt(0))
+        .predicates(vec![Predicate::Field(Field::new("uid"))])
+        .build().unwrap();
+    assert_eq!(
+        block.to_query_string_with(&PrettyConfig::compact()),
+        "popular(func: ge(count(friend), 100)) @filter(gt(len(FRIENDS), 0)) { uid }"
+    );
+
+    let query = Query::parse("{ q(func: ge(count(friend), 5)) @filter(eq(val(AGE), 3) AND lt(len(X), 2)) { uid } }").unwrap();
+    let expected = QueryBuilder::default()
+        .query_blocks(vec![QueryBlockBuilder::default()
+            .query_type(QueryBlockType::named("q"))
+            .root_filter(count("friend").ge(5))
+            .filter(val("AGE").eq(ConditionValue::literal("3")) & len("X").lt(ConditionValue::literal("2")))
+            .predicates(vec![Predicate::Field(Field::new("uid"))])
+            .build().unwrap()])
+        .build().unwrap();
+    assert_eq!(query, expected);
+    assert!(Query::parse("{ q(func: ge(max(friend), 5)) { uid } }").is_err());
+
+    let upsert = UpsertBlock::new(MutationBuilder::default().build().unwrap()).cond(count("friend").eq(0));
+    assert_eq!(serde_json::to_value(&upsert).unwrap()["cond"], "@if(eq(count(friend), 0))");
+}
+
+#[test]
+fn expand_predicates() {
+    let block = QueryBlockBuilder::default()
+        .query_type(QueryBlockType::named("node"))
+        .root_filter(Condition::uid("0x1"))
+        .predicates(vec![
+            Predicate::Expand(Expand::all().predicates(vec![
+                Predicate::Field(Field::new("uid")),
+                Predicate::Expand(Expand::all())
+            ])),
+            Predicate::Expand(Expand::types(&["Person", "Animal"]).filter(has("name")).unwrap()),
+            Predicate::Expand(Expand::val("TYPES"))
+        ])
+        .build().unwrap();
+
+    let rendered = block.to_query_string_with(&PrettyConfig::compact());
+    assert_eq!(
+        rendered,
+        "node(func: uid(0x1)) { expand(_all_) { uid expand(_all_) } expand(Person, Animal) @filter(has(name)) expand(val(TYPES)) }"
+    );
+
+    let query = Query::parse(&format!("{{ {} }}", rendered)).unwrap();
+    let expected = QueryBuilder::default().query_blocks(vec![block]).build().unwrap();
+    assert_eq!(query, expected);
+    assert!(Query::parse("{ q(func: has(name)) { all : expand(_all_) } }").is_err());
+}
+
+#[test]
+fn block_directives() {
+    let block = QueryBlockBuilder::default()
+        .query_type(QueryBlockType::named("people"))
+        .root_filter(has("name"))
+        .filter(has("email"))
+        .directive(Directive::cascade_on(&["name", "http://schema.org/email"]))
+        .directive(Directive::Normalize)
+        .directive(Directive::IgnoreReflex)
+        .predicates(vec![
+            Predicate::Field(Field::new("name").alias("name")),
+            Predicate::Edge(EdgeBuilder::default()
+                .name("friend")
+                .directive(Directive::cascade())
+                .directive(Directive::Normalize)
+                .predicates(vec![Predicate::Field(Field::new("name").alias("friend_name"))])
+                .build().unwrap())
+        ])
+        .build().unwrap();
+
+    let rendered = block.to_query_string_with(&PrettyConfig::compact());
+    assert_eq!(
+        rendered,
+        "people(func: has(name)) @filter(has(email)) @cascade(name, <http://schema.org/email>) @normalize @ignorereflex \
+{ name : name friend @cascade @normalize { friend_name : name } }"
+    );
+
+    let query = Query::parse(&format!("{{ {} }}", rendered)).unwrap();
+    assert_eq!(query, QueryBuilder::default().query_blocks(vec![block]).build().unwrap());
+    assert!(Query::parse("{ q(func: has(name)) @cascade() { uid } }").is_err());
+
+    // Dgraph only takes `@ignorereflex` at the root
+    let edge = EdgeBuilder::default()
+        .name("friend")
+        .directive(Directive::IgnoreReflex)
+        .predicates(vec![Predicate::Field(Field::new("name"))])
+        .build();
+    assert!(edge.unwrap_err().contains("@ignorereflex"));
+    assert!(Query::parse("{ q(func: has(name)) { friend @ignorereflex { name } } }").is_err());
+}
+
+#[test]
+fn root_functions() {
+    let block = |root: RootFunction| QueryBlockBuilder::default()
+        .query_type(QueryBlockType::named("q"))
+        .root_filter(root)
+        .predicates(vec![Predicate::count_uid()])
+        .build();
+
+    let rendered = block(RootFunction::uid(&["PEOPLE", "0x1", "PETS"])).unwrap().to_query_string_with(&PrettyConfig::compact());
+    assert_eq!(rendered, "q(func: uid(PEOPLE, 0x1, PETS)) { count(uid) }");
+
+    let rendered = block(RootFunction::of_type("Person")).unwrap().to_query_string_with(&PrettyConfig::compact());
+    assert_eq!(rendered, "q(func: type(Person)) { count(uid) }");
+    assert_eq!(
+        Query::parse(&format!("{{ {} }}", rendered)).unwrap(),
+        QueryBuilder::default().query_blocks(vec![block(RootFunction::of_type("Person")).unwrap()]).build().unwrap()
+    );
+
+    assert!(block(count("friend").ge(5).into()).is_ok());
+    assert!(block((has("a") & has("b")).into()).is_err());
+    assert!(block((!has("a")).into()).is_err());
+    assert!(block(len("X").gt(1).into()).is_err());
+    assert!(block(RootFunction::uid(&[])).is_err());
+    assert!(Uid::new("0xzz").is_err());
+    assert!(TypeName::new("bad type").is_err());
+    assert!(Query::parse("{ q(func: eq(val(X), 1)) { uid } }").is_err());
+}
+
+#[test]
+fn typed_variables() {
+    let (people, define_people) = Predicate::uid_variable("PEOPLE");
+    let (age, define_age) = Predicate::value_variable("AGE", Field::new("age"));
+    let (friends, define_friends) = Predicate::edge_variable("FRIENDS", EdgeBuilder::default()
+        .name("friend")
+        .predicates(vec![Predicate::Field(Field::new("uid"))])
+        .build().unwrap());
+
+    let query = QueryBuilder::default()
+        .query_blocks(vec![
+            QueryBlockBuilder::default()
+                .query_type(QueryBlockType::var())
+                .root_filter(has("name"))
+                .predicates(vec![define_people, define_age, define_friends])
+                .build().unwrap(),
+            QueryBlockBuilder::default()
+                .query_type(QueryBlockType::named("people"))
+                .root_filter(RootFunction::uid_in(&[&people, &friends]))
+                .filter(val(&age).ge(18) & len(&friends).gt(0))
+                .order(QueryOrder::desc_val(&age))
+                .predicates(vec![Predicate::Val(Variable::from(age.clone()).alias("years"))])
+                .build().unwrap()
+        ])
+        .build().unwrap();
+
+    assert_eq!(query.to_query_string_with(&PrettyConfig::compact()), "{ \
+var(func: has(name)) { PEOPLE as uid AGE as age FRIENDS as friend { uid } } \
+people(func: uid(PEOPLE, FRIENDS), orderdesc: val(AGE)) @filter(ge(val(AGE), 18) AND gt(len(FRIENDS), 0)) { years : val(AGE) } }");
+    assert_eq!(Query::parse(&query.to_query_string()).unwrap(), query);
+
+    assert_eq!(pred("age").eq(&age).to_query_string(), "eq(age, val(AGE))");
+    assert_eq!(Condition::uid_in(&[&people, &friends]).to_query_string(), "uid(PEOPLE, FRIENDS)");
+    assert_eq!(MutationUID::variable(&people).get_uid(), "uid(PEOPLE)");
+}
+
+#[test]
+fn validate_variables() {
+    let validate = |dql: &str| Query::parse(dql).unwrap().validate();
+
+    assert_eq!(validate(r#"
+        query q($min: int) {
+            PEOPLE as var(func: has(name)) {
+                AGE as age
+                FRIENDS as friend { uid }
+            }
+            people(func: uid(PEOPLE), orderasc: val(AGE)) @filter(gt(len(FRIENDS), $min)) { uid }
+        }
+    "#), Ok(()));
+
+    assert_eq!(
+        validate("{ q(func: uid(MISSING)) { uid } }"),
+        Err(ValidationError::UndefinedVariable("MISSING".to_string()))
+    );
+    assert_eq!(
+        validate("{ var(func: has(name)) { UNUSED as uid } q(func: has(name)) { uid } }"),
+        Err(ValidationError::UnusedVariable("UNUSED".to_string()))
+    );
+    assert_eq!(
+        validate("{ var(func: has(name)) { X as uid } var(func: has(age)) { X as age } q(func: uid(X)) { uid } }"),
+        Err(ValidationError::DuplicateVariable("X".to_string()))
+    );
+    assert_eq!(
+        validate("{ var(func: has(name)) { X as uid } q(func: has(name)) { val(X) } }"),
+        Err(ValidationError::NotAValueVariable("X".to_string()))
+    );
+    assert_eq!(
+        validate("{ q(func: has(name)) @filter(eq(name, $name)) { uid } }"),
+        Err(ValidationError::UndefinedParam("name".to_string()))
+    );
+    assert_eq!(
+        validate("{ q(func: has(name)) { uid } q(func: has(age)) { uid } }"),
+        Err(ValidationError::DuplicateBlockName("q".to_string()))
+    );
+
+    // in an upsert the mutations are where query variables get used
+    let query = Query::parse("{ q(func: eq(email, \"a@b.c\")) { USER as uid } }").unwrap();
+    assert!(query.validate().is_err());
+
+    let mutation = MutationBuilder::default()
+        .set(vec![MutationUnit::new(MutationUID::variable("USER")).predicate("seen", MutationPredicateValue::Bool(true))])
+        .build().unwrap();
+    let upsert = Upsert::new(query.clone()).upsert_block(UpsertBlock::new(mutation.clone()));
+    assert_eq!(upsert.validate(), Ok(()));
+    assert_eq!(serde_json::to_value(&upsert).unwrap()["query"], query.to_query_string());
+
+    let upsert = Upsert::new(query).upsert_block(UpsertBlock::new(mutation).cond(len("OTHER").eq(1)));
+    assert_eq!(upsert.validate(), Err(ValidationError::UndefinedVariable("OTHER".to_string())));
+
+    let upsert = Upsert { query: "{ q(func: has(name)) {".to_string(), mutations: vec![] };
+    assert!(matches!(upsert.validate(), Err(ValidationError::InvalidQuery(_))));
+}
+
+#[test]
+fn validate_against_schema() {
+    let schema = Schema::new()
+        .add_definition(SchemaDefinition::new("Person")
+            .add_predicate(PredicateDefinition::new("name", PredicateType::String).add_index(Indexing::HASH))
+            .add_predicate(PredicateDefinition::new("bio", PredicateType::String))
+            .add_predicate(PredicateDefinition::new("age", PredicateType::INT).add_index(Indexing::INT))
+            .add_predicate(PredicateDefinition::new("friend", PredicateType::UIDArray).reverse())
+            .add_predicate(PredicateDefinition::new("pet", PredicateType::UID)));
+
+    let validate = |dql: &str| Query::parse(dql).unwrap().validate_against(&schema);
+
+    assert_eq!(validate(r#"
+        {
+            q(func: eq(name, "bob"), orderasc: age) @filter(ge(age, 18) AND has(bio)) {
+                uid
+                name@en
+                count(friend)
+                dgraph.type
+                ~friend { name }
+                friend @filter(lt(age, 30)) { name }
+            }
+        }
+    "#), Ok(()));
+
+    // comparisons only need an index at the root, regexp needs one everywhere
+    assert_eq!(
+        validate(r#"{ q(func: regexp(name, /^b/)) @filter(eq(bio, "x") AND regexp(bio, /a/)) { nickname pet ~pet { uid } age { uid } } }"#),
+        Err(vec![
+            SchemaError::MissingIndex { function: "regexp".to_string(), predicate: "name".to_string() },
+            SchemaError::MissingIndex { function: "regexp".to_string(), predicate: "bio".to_string() },
+            SchemaError::UnknownPredicate("nickname".to_string()),
+            SchemaError::ScalarSelectionOnUid("pet".to_string()),
+            SchemaError::MissingReverse("pet".to_string()),
+            SchemaError::EdgeOnScalar("age".to_string())
+        ])
+    );
+
+    // comparing a count at the root needs `@count`
+    assert_eq!(
+        validate("{ q(func: ge(count(friend), 2)) @filter(gt(count(pet), 0)) { uid } }"),
+        Err(vec![SchemaError::MissingIndex { function: "count".to_string(), predicate: "friend".to_string() }])
+    );
+    let counted = Schema::new()
+        .add_definition(SchemaDefinition::new("Person")
+            .add_predicate(PredicateDefinition::new("friend", PredicateType::UIDArray).add_index(Indexing::COUNT).reverse()));
+    let query = Query::parse("{ q(func: ge(count(friend), 2)) { uid } }").unwrap();
+    assert_eq!(query.validate_against(&counted), Ok(()));
+    assert!(counted.to_string().contains("friend: [uid] @count @reverse ."));
+
+    // root functions pass `RootFunction::validate` whatever the schema, the index is checked here
+    assert_eq!(RootFunction::from(pred("bio").eq("x")).validate(), Ok(()));
+    assert_eq!(
+        validate(r#"{ q(func: eq(bio, "x")) { uid } }"#),
+        Err(vec![SchemaError::MissingIndex { function: "eq".to_string(), predicate: "bio".to_string() }])
+    );
+
+    assert_eq!(
+        validate("{ q(func: gt(name, \"a\")) { uid } }").unwrap_err()[0].to_string(),
+        "`gt` on `name` needs an index the predicate doesn't have"
+    );
+    assert!(schema.to_string().contains("friend: [uid] @reverse ."));
+
+    let schema = Schema::new()
+        .add_definition(SchemaDefinition::new("Event")
+            .add_predicate(PredicateDefinition::new("score", PredicateType::FLOAT))
+            .add_predicate(PredicateDefinition::new("public", PredicateType::BOOL))
+            .add_predicate(PredicateDefinition::new("start", PredicateType::DATETIME))
+            .add_predicate(PredicateDefinition::new("end", PredicateType::DATETIME).add_index(Indexing::DAY)));
+    let validate = |dql: &str| Query::parse(dql).unwrap().validate_against(&schema);
+
+    assert_eq!(validate(r#"{ q(func: has(score)) @filter(eq(public, true) AND le(start, "2020")) { uid } }"#), Ok(()));
+    assert_eq!(validate(r#"{ q(func: le(end, "2021")) { uid } }"#), Ok(()));
+    for (dql, function, predicate) in &[
+        ("{ q(func: ge(score, 2.5)) { uid } }", "ge", "score"),
+        ("{ q(func: eq(public, true)) { uid } }", "eq", "public"),
+        (r#"{ q(func: le(start, "2020")) { uid } }"#, "le", "start")
+    ] {
+        assert_eq!(validate(dql), Err(vec![
+            SchemaError::MissingIndex { function: function.to_string(), predicate: predicate.to_string() }
+        ]));
+    }
+    assert!(schema.to_string().contains("end: datetime @index(day) ."));
+}
+
+#[test]
+fn validate_mutation_against_schema() {
+    let schema = Schema::new()
+        .add_definition(SchemaDefinition::new("Person")
+            .add_predicate(PredicateDefinition::new("name", PredicateType::String))
+            .add_predicate(PredicateDefinition::new("age", PredicateType::INT))
+            .add_predicate(PredicateDefinition::new("verified", PredicateType::BOOL))
+            .add_predicate(PredicateDefinition::new("height", PredicateType::FLOAT))
+            .add_predicate(PredicateDefinition::new("scores", PredicateType::FLOATArray))
+            .add_predicate(PredicateDefinition::new("friend", PredicateType::UIDArray))
+            .add_predicate(PredicateDefinition::new("pet", PredicateType::UID)));
+
+    let mut generator = NameGenerator::new();
+    let alice = MutationUID::placeholder_with(&mut generator);
+    let bob = MutationUID::uid("0x2");
+
+    let valid = MutationBuilder::default()
+        .set(vec![
+            MutationUnit::new(alice.clone())
+                .predicate("dgraph.type", MutationPredicateValue::string("Person"))
+                .predicate("name", MutationPredicateValue::string("alice"))
+                .predicate("age", MutationPredicateValue::Number(30))
+                .predicate("verified", MutationPredicateValue::Bool(true))
+                .predicate("height", MutationPredicateValue::Number(180))
+                .predicate("scores", MutationPredicateValue::Numbers(vec![1, 2]))
+                .predicate("friend", MutationPredicateValue::Edges(vec![bob.clone()]))
+                .predicate("pet", MutationPredicateValue::Edge(MutationUID::uid("0x3")))
+        ])
+        .delete(vec![MutationUnit::new(bob.clone()).predicate("pet", MutationPredicateValue::Null)])
+        .build().unwrap();
+    assert_eq!(valid.validate_against(&schema), Ok(()));
+
+    let invalid = MutationBuilder::default()
+        .set(vec![
+            MutationUnit::new(alice)
+                .predicate("name", MutationPredicateValue::string("alice"))
+                .predicate("age", MutationPredicateValue::string("thirty"))
+                .predicate("friend", MutationPredicateValue::Edge(bob.clone()))
+                .predicate("nickname", MutationPredicateValue::string("al"))
+                .predicate("pet", MutationPredicateValue::Edges(vec![bob]))
+        ])
+        .build().unwrap();
+    assert_eq!(invalid.validate_against(&schema), Err(vec![
+        SchemaError::MissingType("_:node_0".to_string()),
+        SchemaError::TypeMismatch { predicate: "age".to_string(), expected: PredicateType::INT, found: "string".to_string() },
+        SchemaError::TypeMismatch { predicate: "friend".to_string(), expected: PredicateType::UIDArray, found: "uid".to_string() },
+        SchemaError::UnknownPredicate("nickname".to_string()),
+        SchemaError::ListIntoScalar("pet".to_string())
+    ]));
+}
+
+#[test]
+fn delete_operations() {
+    let alice = MutationUID::uid("0x1");
+    let bob = MutationUID::uid("0x2");
+
+    let mutation = MutationBuilder::default()
+        .set(vec![
+            MutationUnit::new(MutationUID::placeholder_with(&mut NameGenerator::new()))
+                .predicate("name", MutationPredicateValue::string("carol \"c\""))
+                .predicate("age", MutationPredicateValue::Number(30))
+                .predicate("friend", MutationPredicateValue::Edges(vec![alice.clone(), bob.clone()]))
+        ])
+        .delete_node(MutationUID::uid("0x3"))
+        .delete_predicate(alice.clone(), "nickname")
+        .delete_edge(alice.clone(), "friend", bob.clone())
+        .delete_value(bob.clone(), "verified", MutationPredicateValue::Bool(true))
+        .build().unwrap();
+
+    assert_eq!(serde_json::to_value(&mutation).unwrap(), json!({
+        "set": [{"uid": "_:node_0", "name": "carol \"c\"", "age": 30, "friend": [{"uid": "0x1"}, {"uid": "0x2"}]}],
+        "delete": [
+            {"uid": "0x3"},
+            {"uid": "0x1", "nickname": null},
+            {"uid": "0x1", "friend": {"uid": "0x2"}},
+            {"uid": "0x2", "verified": true}
+        ]
+    }));
+
+    assert_eq!(mutation.to_rdf_with(&PrettyConfig::compact()).unwrap(), "{ set { _:node_0 <age> \"30\"^^<xs:int> . _:node_0 <friend> <0x1> . _:node_0 <friend> <0x2> . \
+        _:node_0 <name> \"carol \\\"c\\\"\" . } delete { <0x3> * * . <0x1> <nickname> * . <0x1> <friend> <0x2> . \
+        <0x2> <verified> \"true\"^^<xs:boolean> . } }");
+
+    let upsert = MutationBuilder::default()
+        .set(vec![])
+        .delete_predicate(MutationUID::variable("user"), "email")
+        .build().unwrap();
+    assert_eq!(upsert.to_rdf_with(&PrettyConfig::compact()).unwrap(), "{ delete { uid(user) <email> * . } }");
+}
+
+#[test]
+fn nested_mutations() {
+    let mut generator = NameGenerator::new();
+    let alice = MutationUID::placeholder_with(&mut generator);
+    let pet = MutationUID::placeholder_with(&mut generator);
+
+    let tree = MutationUnit::new(alice.clone())
+        .predicate("name", MutationPredicateValue::string("alice"))
+        .predicate("pet", MutationPredicateValue::Node(MutationUnit::new(pet.clone())
+            .predicate("name", MutationPredicateValue::string("rex"))))
+        .predicate("friend", MutationPredicateValue::Nodes(vec![
+            MutationUnit::new(MutationUID::uid("0x1")),
+            MutationUnit::new(MutationUID::uid("0x2")).predicate("age", MutationPredicateValue::Number(30))
+        ]));
+
+    let mutation = MutationBuilder::default().set(vec![tree.clone()]).build().unwrap();
+    assert_eq!(serde_json::to_value(&mutation).unwrap(), json!({
+        "set": [{
+            "uid": "_:node_0",
+            "name": "alice",
+            "pet": {"uid": "_:node_1", "name": "rex"},
+            "friend": [{"uid": "0x1"}, {"uid": "0x2", "age": 30}]
+        }],
+        "delete": []
+    }));
+
+    assert_eq!(tree.flatten(), vec![
+        MutationUnit::new(alice.clone())
+            .predicate("name", MutationPredicateValue::string("alice"))
+            .predicate("pet", MutationPredicateValue::Edge(pet.clone()))
+            .predicate("friend", MutationPredicateValue::Edges(vec![MutationUID::uid("0x1"), MutationUID::uid("0x2")])),
+        MutationUnit::new(MutationUID::uid("0x2")).predicate("age", MutationPredicateValue::Number(30)),
+        MutationUnit::new(pet).predicate("name", MutationPredicateValue::string("rex"))
+    ]);
+
+    assert_eq!(mutation.to_rdf_with(&PrettyConfig::compact()).unwrap(), "{ set { _:node_0 <friend> <0x1> . \
+        _:node_0 <friend> <0x2> . _:node_0 <name> \"alice\" . _:node_0 <pet> _:node_1 . <0x2> <age> \"30\"^^<xs:int> . \
+        _:node_1 <name> \"rex\" . } }");
+
+    let delete = MutationBuilder::default()
+        .set(vec![])
+        .delete(vec![MutationUnit::new(alice).predicate("pet", MutationPredicateValue::Node(MutationUnit::new(MutationUID::uid("0x3"))))])
+        .build().unwrap();
+    assert_eq!(delete.to_rdf_with(&PrettyConfig::compact()).unwrap(), "{ delete { _:node_0 <pet> <0x3> . } }");
+}
+
+#[test]
+fn reject_invalid_rdf() {
+    use crate::mutation::RdfError;
+
+    let set = |unit: MutationUnit| MutationBuilder::default().set(vec![unit]).build().unwrap();
+    let node = MutationUID::uid("0x1");
+
+    let mutation = set(MutationUnit::new(node.clone()).predicate("name", MutationPredicateValue::string("a")));
+    assert_eq!(mutation.to_rdf_with(&PrettyConfig::compact()), Ok("{ set { <0x1> <name> \"a\" . } }".to_string()));
+
+    // the language tag goes on the string, and only strings can have one
+    let tagged = set(MutationUnit::new(node.clone()).predicate("name@en", MutationPredicateValue::string("a")));
+    assert_eq!(tagged.to_rdf_with(&PrettyConfig::compact()), Ok("{ set { <0x1> <name> \"a\"@en . } }".to_string()));
+    let tagged = set(MutationUnit::new(node.clone()).predicate("age@en", MutationPredicateValue::Number(1)));
+    assert!(matches!(tagged.to_rdf(), Err(RdfError::InvalidPredicate(_))));
+    let tagged = set(MutationUnit::new(node.clone()).predicate("name@e n", MutationPredicateValue::string("a")));
+    assert!(matches!(tagged.to_rdf(), Err(RdfError::InvalidPredicate(_))));
+
+    for value in [
+        MutationPredicateValue::Float(f64::NAN),
+        MutationPredicateValue::Floats(vec![1.0, f64::INFINITY]),
+        MutationPredicateValue::List(vec![ScalarValue::Float(f64::NEG_INFINITY)])
+    ] {
+        let float = set(MutationUnit::new(node.clone()).predicate("score", value));
+        assert_eq!(float.to_rdf(), Err(RdfError::NonFiniteFloat("score".to_string())));
+    }
+
+    let injected = set(MutationUnit::new(node.clone()).predicate("name> \"x\" .\n<0x2> <admin", MutationPredicateValue::Bool(true)));
+    assert!(matches!(injected.to_rdf(), Err(RdfError::InvalidPredicate(_))));
+    let reverse = set(MutationUnit::new(node.clone()).predicate("~friend", MutationPredicateValue::Edge(node.clone())));
+    assert!(matches!(reverse.to_rdf(), Err(RdfError::InvalidPredicate(_))));
+
+    let subject = set(MutationUnit::new(MutationUID::uid("0x1> <name> \"x\" . <0x2")).predicate("name", MutationPredicateValue::string("a")));
+    assert_eq!(subject.to_rdf(), Err(RdfError::InvalidUid("0x1> <name> \"x\" . <0x2".to_string())));
+    let target = set(MutationUnit::new(node.clone()).predicate("friend", MutationPredicateValue::Edges(vec![MutationUID::uid("_:a b")])));
+    assert!(matches!(target.to_rdf(), Err(RdfError::InvalidUid(_))));
+    let variable = set(MutationUnit::new(MutationUID::uid("uid(a) <x")).predicate("name", MutationPredicateValue::string("a")));
+    assert!(matches!(variable.to_rdf(), Err(RdfError::InvalidUid(_))));
+    assert!(set(MutationUnit::new(MutationUID::variable("a")).predicate("name", MutationPredicateValue::string("a"))).to_rdf().is_ok());
+
+    let null = set(MutationUnit::new(node).predicate("name", MutationPredicateValue::Null));
+    assert_eq!(null.to_rdf(), Err(RdfError::NullInSet("name".to_string())));
+}
+
+#[test]
+fn display_mutation_as_json() {
+    // an invalid mutation still displays, since `Display` gives the JSON form
+    let mutation = MutationBuilder::default()
+        .set(vec![MutationUnit::new(MutationUID::uid("0x1>")).predicate("name", MutationPredicateValue::string("a"))])
+        .build().unwrap();
+
+    assert!(mutation.to_rdf().is_err());
+    assert_eq!(mutation.to_string(), serde_json::to_string_pretty(&mutation).unwrap());
+}
+
+#[test]
+fn list_mutation_values() {
+    let node = MutationUID::uid("0x1");
+    let mutation = MutationBuilder::default()
+        .set(vec![
+            MutationUnit::new(node.clone())
+                .predicate("alias", MutationPredicateValue::Strings(vec!["al".to_string(), "ally".to_string()]))
+                .predicate("scores", MutationPredicateValue::Numbers(vec![1, 2]))
+                .predicate("weights", MutationPredicateValue::Floats(vec![0.5]))
+                .predicate("tags", MutationPredicateValue::List(vec![
+                    ScalarValue::string("a"),
+                    ScalarValue::string("b")
+                ]))
+        ])
+        .build().unwrap();
+
+    assert_eq!(serde_json::to_value(&mutation).unwrap(), json!({
+        "set": [{"uid": "0x1", "alias": ["al", "ally"], "scores": [1, 2], "weights": [0.5], "tags": ["a", "b"]}],
+        "delete": []
+    }));
+
+    assert_eq!(mutation.to_rdf_with(&PrettyConfig::compact()).unwrap(), "{ set { <0x1> <alias> \"al\" . \
+        <0x1> <alias> \"ally\" . <0x1> <scores> \"1\"^^<xs:int> . <0x1> <scores> \"2\"^^<xs:int> . \
+        <0x1> <tags> \"a\" . <0x1> <tags> \"b\" . <0x1> <weights> \"0.5\"^^<xs:float> . } }");
+
+    let schema = Schema::new()
+        .add_definition(SchemaDefinition::new("Person")
+            .add_predicate(PredicateDefinition::new("alias", PredicateType::StringArray))
+            .add_predicate(PredicateDefinition::new("scores", PredicateType::INTArray))
+            .add_predicate(PredicateDefinition::new("weights", PredicateType::FLOATArray))
+            .add_predicate(PredicateDefinition::new("tags", PredicateType::StringArray))
+            .add_predicate(PredicateDefinition::new("name", PredicateType::String)));
+    assert_eq!(mutation.validate_against(&schema), Ok(()));
+
+    let invalid = MutationBuilder::default()
+        .set(vec![
+            MutationUnit::new(node)
+                .predicate("name", MutationPredicateValue::Strings(vec!["alice".to_string()]))
+                .predicate("scores", MutationPredicateValue::Strings(vec!["one".to_string()]))
+                .predicate("tags", MutationPredicateValue::List(vec![
+                    ScalarValue::Number(1),
+                    ScalarValue::Bool(true)
+                ]))
+        ])
+        .build().unwrap();
+    assert_eq!(invalid.validate_against(&schema), Err(vec![
+        SchemaError::ListIntoScalar("name".to_string()),
+        SchemaError::TypeMismatch { predicate: "scores".to_string(), expected: PredicateType::INTArray, found: "[string]".to_string() },
+        SchemaError::TypeMismatch { predicate: "tags".to_string(), expected: PredicateType::StringArray, found: "int".to_string() },
+        SchemaError::TypeMismatch { predicate: "tags".to_string(), expected: PredicateType::StringArray, found: "bool".to_string() }
+    ]));
+
+    // list items are scalars, so nested lists, nodes and nulls can't be put in one
+    let mixed = MutationBuilder::default()
+        .set(vec![])
+        .delete(vec![MutationUnit::new(MutationUID::uid("0x1")).predicate("tags", MutationPredicateValue::List(vec![
+            ScalarValue::string("a"),
+            ScalarValue::Number(1),
+            ScalarValue::Float(0.5),
+            ScalarValue::Bool(false)
+        ]))])
+        .build().unwrap();
+    assert_eq!(serde_json::to_value(&mixed).unwrap()["delete"][0]["tags"], json!(["a", 1, 0.5, false]));
+    assert!(mixed.to_rdf().is_ok());
+    assert_eq!(mixed.to_rdf_with(&PrettyConfig::compact()).unwrap(), "{ delete { <0x1> <tags> \"a\" . \
+        <0x1> <tags> \"1\"^^<xs:int> . <0x1> <tags> \"0.5\"^^<xs:float> . <0x1> <tags> \"false\"^^<xs:boolean> . } }");
+}
+
+#[test]
+fn generate_typed_api() {
+    let schema = Schema::new()
+        .add_definition(SchemaDefinition::new("Person")
+            .add_predicate(PredicateDefinition::new("firstName", PredicateType::String))
+            .add_predicate(PredicateDefinition::new("type", PredicateType::String))
+            .add_predicate(PredicateDefinition::new("http://schema.org/age", PredicateType::INT))
+            .add_predicate(PredicateDefinition::new("dgraph.type", PredicateType::StringArray))
+            .add_predicate(PredicateDefinition::new("friend", PredicateType::UIDArray)));
+
+    let code = crate::codegen::generate(&schema);
+    assert!(code.contains("pub struct Person;"));
+    assert!(code.contains("pub const TYPE: &'static str = \"Person\";"));
+    assert!(code.contains("pub fn first_name() -> ::dgraph_query_lib::predicate::Predicate {"));
+    assert!(code.contains("Field::new(\"firstName\")"));
+    assert!(code.contains("pub fn r#type() -> "));
+    assert!(code.contains("pub fn http_schema_org_age() -> "));
+    assert!(code.contains("pub fn friend(select: impl FnOnce(&mut ::dgraph_query_lib::codegen::Selection)) -> "));
+    assert!(!code.contains("dgraph_type"));
+
+    let friend = crate::codegen::Selection::edge("friend", |f| {
+        f.select(Predicate::Field(Field::new("name"))).filter(has("name")).alias("pals");
+    });
+    assert_eq!(friend.unwrap().to_query_string_with(&PrettyConfig::compact()), "pals : friend @filter(has(name)) { name }");
+
+    // invalid selections are reported instead of panicking
+    assert!(crate::codegen::Selection::edge("friend", |f| { f.alias("a b"); }).is_err());
+    assert!(crate::codegen::Selection::edge("friend", |f| { f.filter(Condition::FALSE); }).is_err());
+}
+
+// `generated.rs` is this schema's output checked in, so it's compiled along with the tests
+#[allow(dead_code)]
+mod generated;
+
+#[test]
+fn generated_code_compiles() {
+    let schema = Schema::new()
+        .add_definition(SchemaDefinition::new("Person")
+            .add_predicate(PredicateDefinition::new("firstName", PredicateType::String))
+            .add_predicate(PredicateDefinition::new("first_name", PredicateType::String))
+            .add_predicate(PredicateDefinition::new("self", PredicateType::String))
+            .add_predicate(PredicateDefinition::new("friend", PredicateType::UIDArray)))
+        .add_definition(SchemaDefinition::new("Person"))
+        .add_definition(SchemaDefinition::new("Self"))
+        .add_definition(SchemaDefinition::new("type"))
+        .add_definition(SchemaDefinition::new("2fa-device"));
+
+    assert_eq!(crate::codegen::generate(&schema), include_str!("tests/generated.rs"));
+
+    let friend = generated::Person::friend(|f| {
+        f.select(generated::Person::first_name()).select(generated::Person::first_name_2());
+    }).unwrap();
+    assert_eq!(friend.to_query_string_with(&PrettyConfig::compact()), "friend { firstName first_name }");
+    assert_eq!(generated::Person_2::TYPE, "Person");
+    assert_eq!(generated::Self_::TYPE, "Self");
+    assert_eq!(generated::r#type::TYPE, "type");
+    assert_eq!(generated::_2fa_device::TYPE, "2fa-device");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trip() {
+    let query = Query::parse(r#"
+        query people($min: int = 18) {
+            adults as var(func: ge(age, $min)) { uid }
+            people(func: uid(adults), orderasc: name, first: 10) @filter(has(email) AND eq(len(friends), 2)) @cascade {
+                uid
+                n : name
+                friends @filter(regexp(name, /^a.*$/)) {
+                    name@en
+                }
+            }
+        }
+    "#).unwrap();
+
+    let json = serde_json::to_string(&query).unwrap();
+    let decoded: Query = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(decoded, query);
+    assert_eq!(decoded.to_query_string(), query.to_query_string());
+
+    // names are validated on the way back in
+    let bad = json.replace("\"adults\"", "\"bad name\"");
+    assert!(serde_json::from_str::<Query>(&bad).is_err());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_rejects_unrenderable_values() {
+    use crate::queryblock::UidRef;
+    use crate::predicate::ExpandTarget;
+
+    assert!(serde_json::from_value::<Query>(json!({"name": "a b", "params": [], "query_blocks": []})).is_err());
+    assert!(serde_json::from_value::<Query>(json!({"name": "people", "params": [], "query_blocks": []})).is_ok());
+    assert!(serde_json::from_value::<QueryBlockType>(json!({"Query": "a{b"})).is_err());
+    assert!(serde_json::from_value::<QueryBlockType>(json!({"Query": "people"})).is_ok());
+    assert!(serde_json::from_value::<UidRef>(json!({"Uid": "0x1) { secret }"})).is_err());
+    assert!(serde_json::from_value::<RootFunction>(json!({"Type": "Person)"})).is_err());
+    assert!(serde_json::from_value::<RootFunction>(json!({"Uid": [{"Uid": "abc"}]})).is_err());
+    assert!(serde_json::from_value::<ExpandTarget>(json!({"Types": ["Person", "a)"]})).is_err());
+    assert!(serde_json::from_value::<QueryParam>(json!({
+        "name": "n", "param_type": "Int", "default": "1) { secret }", "value": null
+    })).is_err());
+    assert!(serde_json::from_value::<QueryParam>(json!({
+        "name": "n", "param_type": "Int", "default": "1", "value": null
+    })).is_ok());
+
+    assert!(serde_json::from_value::<ConditionValue>(json!({"Literal": "1) OR has(x"})).is_err());
+    assert!(serde_json::from_value::<ConditionValue>(json!({"NumArr": ["1", "2]"]})).is_err());
+    assert!(serde_json::from_value::<ConditionValue>(json!({"Uid": "0x1)"})).is_err());
+    assert!(serde_json::from_value::<ConditionValue>(json!({"List": [{"List": []}]})).is_err());
+    assert!(serde_json::from_value::<ConditionValue>(json!({"Literal": "42"})).is_ok());
+    assert!(serde_json::from_value::<Condition>(json!({"UID": [{"Uid": "0x1) OR has(x"}]})).is_err());
+    assert!(serde_json::from_value::<Condition>(json!({"UID": [{"Uid": "0x1"}, {"Var": "PEOPLE"}]})).is_ok());
+    assert!(serde_json::from_value::<Condition>(json!({"REGEXP": ["name", {"String": "a"}]})).is_err());
+    assert!(serde_json::from_value::<Condition>(json!({"REGEXP": ["name", {"Regexp": "a"}]})).is_ok());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_runs_builder_validations() {
+    let edge = EdgeBuilder::default()
+        .name("friends")
+        .predicates(vec![Predicate::Field(Field::new("uid"))])
+        .build().unwrap();
+    let mut json = serde_json::to_value(&edge).unwrap();
+    assert!(serde_json::from_value::<crate::predicate::Edge>(json.clone()).is_ok());
+    json["directives"] = json!(["IgnoreReflex"]);
+    assert!(serde_json::from_value::<crate::predicate::Edge>(json).is_err());
+
+    let block = QueryBlockBuilder::default()
+        .query_type(QueryBlockType::query())
+        .root_filter(has("name"))
+        .predicates(vec![Predicate::Field(Field::new("uid"))])
+        .build().unwrap();
+    let mut json = serde_json::to_value(&block).unwrap();
+    assert!(serde_json::from_value::<crate::queryblock::QueryBlock>(json.clone()).is_ok());
+    json["filter"] = serde_json::to_value(Condition::FALSE).unwrap();
+    assert!(serde_json::from_value::<crate::queryblock::QueryBlock>(json).is_err());
+
+    let mut json = serde_json::to_value(Expand::all()).unwrap();
+    assert!(serde_json::from_value::<Expand>(json.clone()).is_ok());
+    json["filter"] = serde_json::to_value(Condition::FALSE).unwrap();
+    assert!(serde_json::from_value::<Expand>(json).is_err());
+}
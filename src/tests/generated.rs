@@ -0,0 +1,62 @@
+// generated from a dgraph schema by dgraph_query_lib::codegen, do not edit
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types)]
+pub struct Person;
+
+#[allow(dead_code)]
+impl Person {
+    pub const TYPE: &'static str = "Person";
+
+    pub fn first_name() -> ::dgraph_query_lib::predicate::Predicate {
+        ::dgraph_query_lib::predicate::Predicate::Field(::dgraph_query_lib::predicate::Field::new("firstName"))
+    }
+
+    pub fn first_name_2() -> ::dgraph_query_lib::predicate::Predicate {
+        ::dgraph_query_lib::predicate::Predicate::Field(::dgraph_query_lib::predicate::Field::new("first_name"))
+    }
+
+    pub fn self_() -> ::dgraph_query_lib::predicate::Predicate {
+        ::dgraph_query_lib::predicate::Predicate::Field(::dgraph_query_lib::predicate::Field::new("self"))
+    }
+
+    pub fn friend(select: impl FnOnce(&mut ::dgraph_query_lib::codegen::Selection)) -> Result<::dgraph_query_lib::predicate::Predicate, String> {
+        ::dgraph_query_lib::codegen::Selection::edge("friend", select)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types)]
+pub struct Person_2;
+
+#[allow(dead_code)]
+impl Person_2 {
+    pub const TYPE: &'static str = "Person";
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types)]
+pub struct Self_;
+
+#[allow(dead_code)]
+impl Self_ {
+    pub const TYPE: &'static str = "Self";
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types)]
+pub struct r#type;
+
+#[allow(dead_code)]
+impl r#type {
+    pub const TYPE: &'static str = "type";
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types)]
+pub struct _2fa_device;
+
+#[allow(dead_code)]
+impl _2fa_device {
+    pub const TYPE: &'static str = "2fa-device";
+}
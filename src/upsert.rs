@@ -1,10 +1,12 @@
 use crate::query::Query;
 use crate::ToQueryString;
 use crate::mutation::Mutation;
-use crate::condition::Condition;
-use serde::Serialize;
+use crate::condition::{Condition, validate_filter};
+use crate::validation::{ValidationError, VariableScope};
+use serde::{Serialize, Serializer};
 
-#[derive(Clone, Serialize)]
+// dgraph takes the query and conditions as DQL text, so that's how they're serialized
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Upsert {
     pub query: String,
     pub mutations: Vec<UpsertBlock>
@@ -26,13 +28,44 @@ impl Upsert {
     pub fn upsert_block_ref(&mut self, block: UpsertBlock) {
         self.mutations.push(block);
     }
+
+    // like `Query::validate`, but variables used by the conditions and
+    // mutations count as uses of the query's variables. the query is kept as
+    // text, so it is parsed again first
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let query = Query::parse(&self.query).map_err(ValidationError::InvalidQuery)?;
+        let mut scope = VariableScope::default();
+        query.collect_variables(&mut scope)?;
+
+        for block in &self.mutations {
+            block.cond.iter().for_each(|cond| cond.collect_variables(&mut scope));
+            block.mutation.collect_variables(&mut scope);
+        }
+
+        scope.check()
+    }
 }
 
+// a condition that always holds is the same as none, and one that never holds would
+// make the mutation pointless, so that is an error
+fn skip_cond(cond: &Option<Condition>) -> bool {
+    cond.as_ref().map(Condition::is_true).unwrap_or(true)
+}
+
+fn serialize_cond<S: Serializer>(cond: &Option<Condition>, serializer: S) -> Result<S::Ok, S::Error> {
+    match cond {
+        Some(cond) => {
+            validate_filter(cond).map_err(serde::ser::Error::custom)?;
+            serializer.serialize_str(&format!("@if({})", cond.to_query_string()))
+        },
+        None => serializer.serialize_none()
+    }
+}
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, Debug, PartialEq)]
 pub struct UpsertBlock {
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub cond: Option<String>,
+    #[serde(skip_serializing_if = "skip_cond", serialize_with = "serialize_cond")]
+    pub cond: Option<Condition>,
     #[serde(flatten)]
     pub mutation: Mutation
 }
@@ -46,7 +79,7 @@ impl UpsertBlock {
     }
 
     pub fn cond(mut self, condition: Condition) -> Self {
-        self.cond = Some(format!("@if({})", condition.to_query_string()));
+        self.cond = Some(condition);
         self
     }
 }
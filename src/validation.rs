@@ -0,0 +1,233 @@
+use crate::names::PredicateName;
+use crate::parser::ParseError;
+use crate::schema::{Indexing, PredicateDefinition, PredicateType, Schema};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ValidationError {
+    UndefinedVariable(String),
+    UnusedVariable(String),
+    DuplicateVariable(String),
+    NotAValueVariable(String),
+    UndefinedParam(String),
+    DuplicateBlockName(String),
+    InvalidQuery(ParseError)
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::UndefinedVariable(name) => write!(f, "variable `{}` is used but never defined", name),
+            ValidationError::UnusedVariable(name) => write!(f, "variable `{}` is defined but never used", name),
+            ValidationError::DuplicateVariable(name) => write!(f, "variable `{}` is defined more than once", name),
+            ValidationError::NotAValueVariable(name) => write!(f, "`val({})` needs a value variable but `{}` holds uids", name, name),
+            ValidationError::UndefinedParam(name) => write!(f, "parameter `${}` is used but never declared", name),
+            ValidationError::DuplicateBlockName(name) => write!(f, "more than one query block is named `{}`", name),
+            ValidationError::InvalidQuery(err) => write!(f, "the query doesn't parse: {}", err)
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SchemaError {
+    UnknownPredicate(String),
+    EdgeOnScalar(String),
+    ScalarSelectionOnUid(String),
+    MissingIndex { function: String, predicate: String },
+    MissingReverse(String),
+    TypeMismatch { predicate: String, expected: PredicateType, found: String },
+    ListIntoScalar(String),
+    MissingType(String)
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::UnknownPredicate(name) => write!(f, "predicate `{}` is not in the schema", name),
+            SchemaError::EdgeOnScalar(name) => write!(f, "`{}` is a scalar predicate but has a sub-selection", name),
+            SchemaError::ScalarSelectionOnUid(name) => write!(f, "`{}` is a uid predicate but has no sub-selection", name),
+            SchemaError::MissingIndex { function, predicate } => {
+                write!(f, "`{}` on `{}` needs an index the predicate doesn't have", function, predicate)
+            },
+            SchemaError::MissingReverse(name) => write!(f, "`~{}` needs `{}` to be declared with @reverse", name, name),
+            SchemaError::TypeMismatch { predicate, expected, found } => {
+                write!(f, "`{}` is of type {} but was given a {} value", predicate, expected, found)
+            },
+            SchemaError::ListIntoScalar(name) => write!(f, "`{}` holds a single value but was given a list", name),
+            SchemaError::MissingType(uid) => write!(f, "new node `{}` has no dgraph.type", uid)
+        }
+    }
+}
+
+impl Error for SchemaError {}
+
+// the schema checks walk the query much like the variable checks do, pushing
+// every problem found instead of stopping at the first one
+pub(crate) struct SchemaCheck<'a> {
+    schema: &'a Schema,
+    pub(crate) errors: Vec<SchemaError>
+}
+
+impl<'a> SchemaCheck<'a> {
+    pub(crate) fn new(schema: &'a Schema) -> Self {
+        SchemaCheck {
+            schema,
+            errors: vec![]
+        }
+    }
+
+    // reports unknown predicates and reverse edges without @reverse. reverse
+    // edges and built in predicates have no definition to check against
+    fn lookup(&mut self, name: &PredicateName) -> Option<&'a PredicateDefinition> {
+        let name = name.as_str();
+        let name = name.split('@').next().unwrap_or(name);
+        let (reverse, name) = match name.strip_prefix('~') {
+            Some(name) => (true, name),
+            None => (false, name)
+        };
+
+        if name == "uid" || name.starts_with("dgraph.") {
+            return None;
+        }
+
+        match self.schema.predicate(name) {
+            None => {
+                self.errors.push(SchemaError::UnknownPredicate(name.to_string()));
+                None
+            },
+            Some(def) if reverse && !def.is_reverse() => {
+                self.errors.push(SchemaError::MissingReverse(name.to_string()));
+                None
+            },
+            // a reversed edge always points at nodes
+            Some(_) if reverse => None,
+            Some(def) => Some(def)
+        }
+    }
+
+    pub(crate) fn predicate(&mut self, name: &PredicateName) {
+        self.lookup(name);
+    }
+
+    pub(crate) fn field(&mut self, name: &PredicateName) {
+        if let Some(def) = self.lookup(name) {
+            if def.predicate_type().is_uid() {
+                self.errors.push(SchemaError::ScalarSelectionOnUid(def.name().to_string()));
+            }
+        }
+    }
+
+    pub(crate) fn edge(&mut self, name: &PredicateName) {
+        if let Some(def) = self.lookup(name) {
+            if !def.predicate_type().is_uid() {
+                self.errors.push(SchemaError::EdgeOnScalar(def.name().to_string()));
+            }
+        }
+    }
+
+    // dgraph needs an index for comparisons at the root only, filters compare the
+    // values of the nodes already found. regexp needs its index everywhere
+    pub(crate) fn function(&mut self, function: &str, name: &PredicateName, root: bool) {
+        let def = match self.lookup(name) {
+            Some(def) => def,
+            None => return
+        };
+
+        let required: &[Indexing] = match (function, def.predicate_type()) {
+            ("regexp", _) => &[Indexing::TRIGRAM],
+            _ if !root => &[],
+            ("eq", PredicateType::String) | ("eq", PredicateType::StringArray) => &[Indexing::EXACT, Indexing::HASH, Indexing::TERM],
+            (_, PredicateType::String) | (_, PredicateType::StringArray) => &[Indexing::EXACT],
+            (_, PredicateType::INT) | (_, PredicateType::INTArray) => &[Indexing::INT],
+            (_, PredicateType::FLOAT) | (_, PredicateType::FLOATArray) => &[Indexing::FLOAT],
+            (_, PredicateType::BOOL) => &[Indexing::BOOL],
+            (_, PredicateType::DATETIME) => &[Indexing::YEAR, Indexing::MONTH, Indexing::DAY, Indexing::HOUR],
+            _ => &[]
+        };
+
+        self.require_index(function, def, required);
+    }
+
+    // comparing `count(predicate)` at the root needs `@count`
+    pub(crate) fn count(&mut self, name: &PredicateName, root: bool) {
+        if let Some(def) = self.lookup(name) {
+            if root {
+                self.require_index("count", def, &[Indexing::COUNT]);
+            }
+        }
+    }
+
+    fn require_index(&mut self, function: &str, def: &PredicateDefinition, required: &[Indexing]) {
+        if !required.is_empty() && !required.iter().any(|index| def.indexing().contains(index)) {
+            self.errors.push(SchemaError::MissingIndex {
+                function: function.to_string(),
+                predicate: def.name().to_string()
+            });
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum VariableKind {
+    Uid,
+    Value
+}
+
+// definitions and uses of variables and parameters, collected by walking a
+// query (and the mutations of an upsert) and checked against each other at the end
+#[derive(Debug, Default)]
+pub(crate) struct VariableScope {
+    definitions: Vec<(String, VariableKind)>,
+    uses: Vec<(String, VariableKind)>,
+    params: Vec<String>,
+    param_uses: Vec<String>
+}
+
+impl VariableScope {
+    pub(crate) fn define(&mut self, name: &str, kind: VariableKind) {
+        self.definitions.push((name.to_string(), kind));
+    }
+
+    // `uid(x)` and `len(x)` accept either kind, `val(x)` needs a value variable
+    pub(crate) fn use_variable(&mut self, name: &str, kind: VariableKind) {
+        self.uses.push((name.to_string(), kind));
+    }
+
+    pub(crate) fn declare_param(&mut self, name: &str) {
+        self.params.push(name.to_string());
+    }
+
+    pub(crate) fn use_param(&mut self, name: &str) {
+        self.param_uses.push(name.to_string());
+    }
+
+    pub(crate) fn check(&self) -> Result<(), ValidationError> {
+        for (index, (name, _)) in self.definitions.iter().enumerate() {
+            if self.definitions[..index].iter().any(|(defined, _)| defined == name) {
+                return Err(ValidationError::DuplicateVariable(name.clone()));
+            }
+        }
+
+        for (name, kind) in &self.uses {
+            match self.definitions.iter().find(|(defined, _)| defined == name) {
+                None => return Err(ValidationError::UndefinedVariable(name.clone())),
+                Some((_, VariableKind::Uid)) if *kind == VariableKind::Value => {
+                    return Err(ValidationError::NotAValueVariable(name.clone()));
+                },
+                Some(_) => ()
+            }
+        }
+
+        if let Some((name, _)) = self.definitions.iter().find(|(defined, _)| !self.uses.iter().any(|(used, _)| used == defined)) {
+            return Err(ValidationError::UnusedVariable(name.clone()));
+        }
+
+        match self.param_uses.iter().find(|name| !self.params.contains(name)) {
+            Some(name) => Err(ValidationError::UndefinedParam(name.clone())),
+            None => Ok(())
+        }
+    }
+}